@@ -0,0 +1,396 @@
+//! JSON document keeping all its nodes and strings in an arena
+use std::collections::HashMap;
+
+use crate::{DuplicateKeys, JsonData, Lexer, Members, ParseOptions, Position, Tok};
+
+/// A node of the arena, containers are followed by their members, and
+/// `end` is an index past the last of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Node {
+    Obj { len: usize, end: usize },
+    Arr { len: usize, end: usize },
+    Key(usize, usize),
+    Text(usize, usize),
+    Num(f64),
+    Bool(bool),
+    Null,
+}
+
+/// JSON document stored in two flat buffers
+///
+/// All nodes live in one `Vec` and all strings and keys in one `String`,
+/// so a document of any size is built with a few growing allocations and
+/// dropped at once. Values are navigated through lightweight [JsonNode]
+/// handles.
+/// ```
+/// let doc = simjson::JsonDoc::parse(r#"{"name":"Malvika", "kids":["Ruth", "Josef"]}"#).unwrap();
+/// let root = doc.root().unwrap();
+/// assert_eq!(root.get("name").and_then(|name| name.as_str()), Some("Malvika"));
+/// assert_eq!(root.path(&"kids/1").and_then(|kid| kid.as_str()), Some("Josef"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonDoc {
+    nodes: Vec<Node>,
+    strings: String,
+    duplicate_keys: DuplicateKeys,
+}
+
+impl JsonDoc {
+    /// Parses the first JSON value of the text
+    pub fn parse(json: &str) -> Result<Self, String> {
+        Self::parse_with(json, &ParseOptions::default())
+    }
+
+    /// Parses the first JSON value of the text applying the options
+    ///
+    /// All members of an object are kept, [ParseOptions::duplicate_keys]
+    /// decides which one [JsonNode::get] returns and which ones go to
+    /// `JsonData`.
+    pub fn parse_with(json: &str, options: &ParseOptions) -> Result<Self, String> {
+        Self::build(json, options, false)
+    }
+
+    /// Parses the first JSON value of the text storing every distinct key
+    /// once, all objects having it refer to the same copy
+    ///
+    /// It saves memory for arrays of records of the same schema at a cost
+    /// of looking every key up.
+    pub fn parse_interned(json: &str, options: &ParseOptions) -> Result<Self, String> {
+        Self::build(json, options, true)
+    }
+
+    fn build(json: &str, options: &ParseOptions, intern_keys: bool) -> Result<Self, String> {
+        let mut doc = JsonDoc {
+            duplicate_keys: options.duplicate_keys,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::with_options(options);
+        let mut open = Vec::new();
+        let mut keys = intern_keys.then(HashMap::new);
+        let mut at = 0;
+        let done = |doc: &JsonDoc, open: &Vec<_>| !doc.nodes.is_empty() && open.is_empty();
+        while at < json.len() && !done(&doc, &open) {
+            for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+                if done(&doc, &open) {
+                    break;
+                }
+                doc.token(tok, &mut lexer, &mut open, &mut keys)?
+            }
+        }
+        if !done(&doc, &open)
+            && let Some(tok) = lexer.finish()
+        {
+            doc.token(tok, &mut lexer, &mut open, &mut keys)?
+        }
+        doc.nodes.shrink_to_fit();
+        doc.strings.shrink_to_fit();
+        Ok(doc)
+    }
+
+    /// Returns a number of bytes allocated by the document
+    pub fn allocated(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node>() + self.strings.capacity()
+    }
+
+    /// Adds a node of the token, open containers come with starts of their
+    /// keys kept for [DuplicateKeys::Error]
+    fn token(
+        &mut self,
+        tok: Tok,
+        lexer: &mut Lexer,
+        open: &mut Vec<(usize, Option<HashMap<String, Position>>)>,
+        keys: &mut Option<HashMap<String, (usize, usize)>>,
+    ) -> Result<(), String> {
+        if !matches!(tok, Tok::Key | Tok::EndObject | Tok::EndArray)
+            && let Some(&(container, _)) = open.last()
+        {
+            match &mut self.nodes[container] {
+                Node::Obj { len, .. } | Node::Arr { len, .. } => *len += 1,
+                _ => unreachable!(),
+            }
+        }
+        let node = match tok {
+            Tok::StartObject | Tok::StartArray => {
+                open.push((self.nodes.len(), None));
+                match tok {
+                    Tok::StartObject => Node::Obj { len: 0, end: 0 },
+                    _ => Node::Arr { len: 0, end: 0 },
+                }
+            }
+            Tok::EndObject | Tok::EndArray => {
+                let (container, _) = open.pop().expect("the lexer balances containers");
+                let next = self.nodes.len();
+                match &mut self.nodes[container] {
+                    Node::Obj { end, .. } | Node::Arr { end, .. } => *end = next,
+                    _ => unreachable!(),
+                }
+                return Ok(());
+            }
+            Tok::Key => {
+                if self.duplicate_keys == DuplicateKeys::Error
+                    && let Some((_, starts)) = open.last_mut()
+                {
+                    let starts = starts.get_or_insert_default();
+                    if let Some(&first) = starts.get(&lexer.text) {
+                        let key = lexer.text.clone();
+                        return Err(lexer.duplicate(&key, first));
+                    }
+                    starts.insert(lexer.text.clone(), lexer.mark);
+                }
+                let text = &lexer.text;
+                let (start, end) = match keys {
+                    Some(keys) => match keys.get(text) {
+                        Some(&span) => span,
+                        None => {
+                            let span = self.store(text);
+                            keys.insert(text.to_string(), span);
+                            span
+                        }
+                    },
+                    None => self.store(text),
+                };
+                Node::Key(start, end)
+            }
+            Tok::Str => {
+                let (start, end) = self.store(&lexer.text);
+                Node::Text(start, end)
+            }
+            Tok::Num(num) => Node::Num(num),
+            Tok::Bool(b) => Node::Bool(b),
+            Tok::Null => Node::Null,
+            Tok::Err(err) => return Err(err),
+        };
+        self.nodes.push(node);
+        Ok(())
+    }
+
+    fn store(&mut self, text: &str) -> (usize, usize) {
+        let start = self.strings.len();
+        self.strings.push_str(text);
+        (start, self.strings.len())
+    }
+
+    /// Returns the top value, or `None` for an empty document
+    pub fn root(&self) -> Option<JsonNode<'_>> {
+        self.nodes.first().map(|_| JsonNode { doc: self, inx: 0 })
+    }
+
+    /// Returns a value of the slash separated path from the root
+    pub fn path(&self, path: &impl AsRef<str>) -> Option<JsonNode<'_>> {
+        self.root()?.path(path)
+    }
+
+    fn append(&mut self, json: &JsonData) {
+        let inx = self.nodes.len();
+        match json {
+            JsonData::Data(obj) => {
+                self.nodes.push(Node::Obj {
+                    len: obj.len(),
+                    end: 0,
+                });
+                for (key, value) in obj {
+                    let (start, end) = self.store(key);
+                    self.nodes.push(Node::Key(start, end));
+                    self.append(value)
+                }
+                let next = self.nodes.len();
+                self.nodes[inx] = Node::Obj {
+                    len: obj.len(),
+                    end: next,
+                }
+            }
+            JsonData::Arr(arr) => {
+                self.nodes.push(Node::Arr {
+                    len: arr.len(),
+                    end: 0,
+                });
+                arr.iter().for_each(|value| self.append(value));
+                let next = self.nodes.len();
+                self.nodes[inx] = Node::Arr {
+                    len: arr.len(),
+                    end: next,
+                }
+            }
+            JsonData::Text(text) => {
+                let (start, end) = self.store(text);
+                self.nodes.push(Node::Text(start, end))
+            }
+            JsonData::Num(num) => self.nodes.push(Node::Num(*num)),
+            JsonData::Bool(b) => self.nodes.push(Node::Bool(*b)),
+            JsonData::Null | JsonData::None | JsonData::Err(_) => self.nodes.push(Node::Null),
+        }
+    }
+}
+
+/// Copies `JsonData` to the arena, a top level [JsonData::None] or
+/// [JsonData::Err] gives an empty document and nested ones become null
+impl From<&JsonData> for JsonDoc {
+    fn from(json: &JsonData) -> Self {
+        let mut doc = JsonDoc::default();
+        if !matches!(json, JsonData::None | JsonData::Err(_)) {
+            doc.append(json)
+        }
+        doc
+    }
+}
+
+impl From<&JsonDoc> for JsonData {
+    fn from(doc: &JsonDoc) -> Self {
+        doc.root().map_or(JsonData::None, |root| root.to_data())
+    }
+}
+
+/// A handle of a value in [JsonDoc]
+#[derive(Debug, Clone, Copy)]
+pub struct JsonNode<'d> {
+    doc: &'d JsonDoc,
+    inx: usize,
+}
+
+impl<'d> JsonNode<'d> {
+    fn node(&self) -> Node {
+        self.doc.nodes[self.inx]
+    }
+
+    /// Returns an index past the value and its members
+    fn next(&self) -> usize {
+        match self.node() {
+            Node::Obj { end, .. } | Node::Arr { end, .. } => end,
+            _ => self.inx + 1,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'d str> {
+        match self.node() {
+            Node::Text(start, end) => Some(&self.doc.strings[start..end]),
+            _ => None,
+        }
+    }
+
+    pub fn as_num(&self) -> Option<f64> {
+        match self.node() {
+            Node::Num(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.node() {
+            Node::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.node() == Node::Null
+    }
+
+    pub fn is_object(&self) -> bool {
+        matches!(self.node(), Node::Obj { .. })
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self.node(), Node::Arr { .. })
+    }
+
+    /// Returns a number of members of an object or elements of an array
+    pub fn len(&self) -> usize {
+        match self.node() {
+            Node::Obj { len, .. } | Node::Arr { len, .. } => len,
+            _ => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a member value of an object, the last one of a repeated
+    /// key with [DuplicateKeys::LastWins] and the first one otherwise
+    pub fn get(&self, key: &str) -> Option<JsonNode<'d>> {
+        let mut found = self.members().filter(|(name, _)| *name == key);
+        match self.doc.duplicate_keys {
+            DuplicateKeys::LastWins => found.last(),
+            _ => found.next(),
+        }
+        .map(|(_, value)| value)
+    }
+
+    /// Returns an element of an array
+    pub fn at(&self, index: usize) -> Option<JsonNode<'d>> {
+        self.elements().nth(index)
+    }
+
+    /// Returns a value of the slash separated path, array elements are
+    /// addressed by index
+    pub fn path(&self, path: &impl AsRef<str>) -> Option<JsonNode<'d>> {
+        let mut node = *self;
+        for comp in path.as_ref().split('/') {
+            node = match node.node() {
+                Node::Obj { .. } => node.get(comp)?,
+                Node::Arr { .. } => node.at(comp.parse().ok()?)?,
+                _ => return None,
+            }
+        }
+        Some(node)
+    }
+
+    /// Iterates over elements of an array, nothing for other values
+    pub fn elements(&self) -> impl Iterator<Item = JsonNode<'d>> + 'd {
+        let (doc, end) = match self.node() {
+            Node::Arr { end, .. } => (self.doc, end),
+            _ => (self.doc, self.inx + 1),
+        };
+        let mut inx = self.inx + 1;
+        std::iter::from_fn(move || {
+            if inx >= end {
+                return None;
+            }
+            let node = JsonNode { doc, inx };
+            inx = node.next();
+            Some(node)
+        })
+    }
+
+    /// Iterates over keys and values of an object, nothing for other values
+    pub fn members(&self) -> impl Iterator<Item = (&'d str, JsonNode<'d>)> + 'd {
+        let (doc, end) = match self.node() {
+            Node::Obj { end, .. } => (self.doc, end),
+            _ => (self.doc, self.inx + 1),
+        };
+        let mut inx = self.inx + 1;
+        std::iter::from_fn(move || {
+            if inx >= end {
+                return None;
+            }
+            let Node::Key(start, key_end) = doc.nodes[inx] else {
+                unreachable!("a member starts with a key")
+            };
+            let value = JsonNode { doc, inx: inx + 1 };
+            inx = value.next();
+            Some((&doc.strings[start..key_end], value))
+        })
+    }
+
+    /// Copies the value to `JsonData`
+    pub fn to_data(&self) -> JsonData {
+        match self.node() {
+            Node::Obj { len, .. } => {
+                let mut members = Members {
+                    obj: HashMap::with_capacity(len),
+                    ..Default::default()
+                };
+                for (key, value) in self.members() {
+                    members.key = key.to_string();
+                    members.insert(value.to_data(), self.doc.duplicate_keys)
+                }
+                JsonData::Data(members.obj)
+            }
+            Node::Arr { .. } => JsonData::Arr(self.elements().map(|value| value.to_data()).collect()),
+            Node::Text(..) | Node::Key(..) => JsonData::Text(self.as_str().unwrap_or_default().to_string()),
+            Node::Num(num) => JsonData::Num(num),
+            Node::Bool(b) => JsonData::Bool(b),
+            Node::Null => JsonData::Null,
+        }
+    }
+}
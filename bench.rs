@@ -0,0 +1,405 @@
+//! Parsing time measurements of simjson
+//!
+//! Build the crate and the program in release mode and run it,
+//! ```sh
+//! export VERSION=bench
+//! rustc -C opt-level=3 --edition 2024 --crate-type lib --crate-name simjson lib.rs
+//! rustc -C opt-level=3 --edition 2024 bench.rs --extern simjson=libsimjson.rlib
+//! ./bench > bench_output.txt
+//! ```
+//! All corpora are generated, nothing is downloaded. Giving an output of
+//! an earlier run as an argument, `./bench bench_output.txt`, adds
+//! a change of every throughput and marks a slowdown over 10% as
+//! a regression.
+//!
+//! The scanner section puts `parse` over `&str`, which copies string runs
+//! byte wise, next to `parse_fragment` over a `char` iterator, which goes
+//! through the per char state machine.
+//!
+//! Every scaling series doubles the input size, time per byte staying
+//! flat indicates a linear behavior. The cloning column builds the same
+//! tree copying every completed container into its parent, as the parser
+//! did before values were moved, it is measured up to 4000 items only.
+use std::{
+    collections::HashMap,
+    fs,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use simjson::{Event, JsonData, NonFinite, ParseOptions, WriteOptions};
+
+const ROUNDS: u32 = 5;
+
+/// A slowdown in percent reported as a regression
+const TOLERANCE: f64 = 10.0;
+
+/// A largest series size built by cloning, deep nesting gets quadratic
+const CLONING_LIMIT: usize = 4_000;
+
+fn main() {
+    let baseline = std::env::args()
+        .nth(1)
+        .map(|file| read_baseline(&file))
+        .unwrap_or_default();
+    println!("simjson {}", simjson::VERSION);
+    let regressions = throughput(&baseline);
+    scanner();
+    println!("scaling");
+    series("nested arrays", |n| "[".repeat(n) + &"]".repeat(n));
+    series("nested objects", |n| {
+        "{\"a\":".repeat(n) + "1" + &"}".repeat(n)
+    });
+    series("wide array", |n| {
+        let mut json = String::from("[");
+        for i in 0..n {
+            if i > 0 {
+                json.push(',')
+            }
+            json.push_str(&format!(r#"{{"id":{i},"name":"item {i}","tags":["a","b"]}}"#))
+        }
+        json.push(']');
+        json
+    });
+    series("wide object", |n| {
+        let mut json = String::from("{");
+        for i in 0..n {
+            if i > 0 {
+                json.push(',')
+            }
+            json.push_str(&format!(r#""key{i}":[{i},true,null]"#))
+        }
+        json.push('}');
+        json
+    });
+    arena();
+    if regressions > 0 {
+        println!("{regressions} regression(s)");
+        std::process::exit(1)
+    }
+}
+
+/// Generates the corpora, a couple of megabytes each
+fn corpora() -> Vec<(&'static str, String)> {
+    let mut random = Random(0x2545_F491_4F6C_DD1D);
+    let array = |items: Vec<String>| format!("[{}]", items.join(","));
+    let record = |i: usize| {
+        format!(
+            r#"{{"id":{i},"name":"user {i}","active":{},"score":{}.5,"tags":["a","b"],"address":{{"city":"Delhi","zip":null}}}}"#,
+            i.is_multiple_of(2),
+            i % 100
+        )
+    };
+    let nested = array(
+        (0..2_000)
+            .map(|i| "{\"level\":[".repeat(50) + &i.to_string() + &"]}".repeat(50))
+            .collect(),
+    );
+    let wide = format!(
+        "{{{}}}",
+        (0..80_000)
+            .map(|i| format!("\"key_{i}\":\"value {i}\""))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let numbers = array(
+        (0..150_000)
+            .map(|i| match i % 4 {
+                0 => random.next().to_string(),
+                1 => format!("-{}.{}", random.next() % 1_000, random.next() % 100_000),
+                2 => format!("{}.{}e{}", random.next() % 10, random.next() % 1_000, random.next() % 300),
+                _ => format!("{}E-{}", random.next() % 100, random.next() % 20),
+            })
+            .collect(),
+    );
+    let strings = array(
+        (0..40_000)
+            .map(|i| format!(r#""line {i}\n\t\"quoted\" path C:\\dir\\file\/name \u00e9\u00e8 end""#))
+            .collect(),
+    );
+    let unicode = array(
+        (0..40_000)
+            .map(|i| match i % 3 {
+                0 => "\"Добрий день, світе\"".to_string(),
+                1 => "\"こんにちは世界 👧🏽 नमस्ते\"".to_string(),
+                _ => r#""\ud83d\ude00 smile \u4e16\u754c""#.to_string(),
+            })
+            .collect(),
+    );
+    vec![
+        ("nested", nested),
+        ("wide", wide),
+        ("numbers", numbers),
+        ("strings", strings),
+        ("unicode", unicode),
+        ("records", array((0..20_000).map(record).collect())),
+        ("ndjson", (0..20_000).map(record).collect::<Vec<_>>().join("\n")),
+    ]
+}
+
+/// Prints MB/s of every API for every corpus and returns a number of
+/// regressions against the baseline
+///
+/// Parsers are measured by input bytes, serializers write the parsed
+/// values back and are measured by output bytes.
+fn throughput(baseline: &HashMap<String, f64>) -> usize {
+    println!("{:<8} {:<14} {:>10} {:>10}", "corpus", "api", "MB/s", "change");
+    let options = ParseOptions::default();
+    let mut regressions = 0;
+    for (corpus, json) in corpora() {
+        let mut apis: Vec<(&str, Duration)> = Vec::new();
+        let values = match corpus {
+            "ndjson" => simjson::parse_lines(&json, 1).into_iter().map(|(_, value)| value).collect(),
+            _ => vec![simjson::parse_with(&json, &options)],
+        };
+        if corpus == "ndjson" {
+            apis.push(("parse_lines/1", measure(|| lines(simjson::parse_lines(&json, 1)))));
+            apis.push(("parse_lines", measure(|| lines(simjson::parse_lines(&json, 0)))));
+        } else {
+            apis.push(("parse", measure(|| simjson::parse_with(&json, &options))));
+            apis.push((
+                "parse_fragment",
+                measure(|| simjson::parse_fragment_with(&mut json.chars(), &options).0),
+            ));
+            apis.push((
+                "parse_ref",
+                measure(|| match simjson::parse_ref_with(&json, &options) {
+                    simjson::JsonRef::Err(err) => JsonData::Err(err),
+                    _ => JsonData::Null,
+                }),
+            ));
+            apis.push((
+                "LazyJson",
+                measure(|| result(simjson::LazyJson::with_options(&json, &options))),
+            ));
+            apis.push((
+                "JsonDoc",
+                measure(|| result(simjson::JsonDoc::parse_with(&json, &options))),
+            ));
+        }
+        let mut written = 0;
+        for (api, write) in [
+            ("to_json", WriteOptions::default()),
+            (
+                "to_json_with",
+                WriteOptions {
+                    non_finite: NonFinite::Literal,
+                },
+            ),
+        ] {
+            written = values.iter().map(|value| simjson::to_json_with(value, &write).len()).sum();
+            apis.push((
+                api,
+                measure(|| {
+                    for value in &values {
+                        black_box(simjson::to_json_with(value, &write));
+                    }
+                    JsonData::Null
+                }),
+            ));
+        }
+        for (api, time) in apis {
+            let len = if api.starts_with("to_json") { written } else { json.len() };
+            let mbs = len as f64 / time.as_secs_f64() / 1e6;
+            let change = match baseline.get(&format!("{corpus} {api}")) {
+                Some(base) => {
+                    let change = (mbs / base - 1.0) * 100.0;
+                    let regression = change < -TOLERANCE;
+                    regressions += regression as usize;
+                    format!("{change:>+9.1}%{}", if regression { " regression" } else { "" })
+                }
+                None => String::new(),
+            };
+            println!("{corpus:<8} {api:<14} {mbs:>10.1} {change}")
+        }
+    }
+    regressions
+}
+
+/// Compares the byte scanner of `parse` with the char state machine
+/// used for `char` iterators on the same corpora
+fn scanner() {
+    println!("byte scanner vs char iterator");
+    let options = ParseOptions::default();
+    for (corpus, json) in corpora() {
+        if corpus == "ndjson" {
+            continue;
+        }
+        let bytes = measure(|| simjson::parse_with(&json, &options));
+        let chars = measure(|| simjson::parse_fragment_with(&mut json.chars(), &options).0);
+        println!(
+            "{corpus:<8} {:>10} bytes {:>8.1} MB/s bytes {:>8.1} MB/s chars {:>6.2}x",
+            json.len(),
+            json.len() as f64 / bytes.as_secs_f64() / 1e6,
+            json.len() as f64 / chars.as_secs_f64() / 1e6,
+            chars.as_secs_f64() / bytes.as_secs_f64()
+        )
+    }
+}
+
+/// Reads throughputs of an earlier run
+fn read_baseline(file: &str) -> HashMap<String, f64> {
+    let text = fs::read_to_string(file).unwrap_or_else(|err| panic!("can't read {file}: {err}"));
+    text.lines()
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let (corpus, api, mbs) = (cols.next()?, cols.next()?, cols.next()?);
+            Some((format!("{corpus} {api}"), mbs.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Turns results of JSON Lines parsing to the first error if any
+fn lines(values: Vec<(usize, JsonData)>) -> JsonData {
+    values
+        .into_iter()
+        .map(|(_, value)| value)
+        .find(|value| matches!(value, JsonData::Err(_)))
+        .unwrap_or(JsonData::Null)
+}
+
+fn result<T>(res: Result<T, String>) -> JsonData {
+    match res {
+        Ok(_) => JsonData::Null,
+        Err(err) => JsonData::Err(err),
+    }
+}
+
+fn series(name: &str, input: impl Fn(usize) -> String) {
+    println!("{name}");
+    let options = ParseOptions {
+        max_depth: usize::MAX,
+        ..Default::default()
+    };
+    for n in [1_000, 2_000, 4_000, 8_000, 16_000] {
+        let json = input(n);
+        let time = measure(|| simjson::parse_with(&json, &options));
+        let cloned = match n <= CLONING_LIMIT {
+            true => {
+                let cloned = measure(|| cloning(&json, &options));
+                format!(
+                    "{:>10.3} ms {:>10.2} ns/byte",
+                    cloned.as_secs_f64() * 1e3,
+                    cloned.as_nanos() as f64 / json.len() as f64
+                )
+            }
+            false => "-".to_string(),
+        };
+        println!(
+            "{n:>8} {:>10} bytes {:>10.3} ms {:>8.2} ns/byte, cloning {cloned}",
+            json.len(),
+            time.as_secs_f64() * 1e3,
+            time.as_nanos() as f64 / json.len() as f64
+        )
+    }
+}
+
+/// Builds `JsonData` from reader events copying every completed container
+/// into its parent, the way the parser did before values were moved
+fn cloning(json: &str, options: &ParseOptions) -> JsonData {
+    let mut reader = simjson::JsonReader::with_options(json.chars(), options);
+    let mut stack: Vec<(JsonData, Option<String>)> = Vec::new();
+    while let Some((event, _)) = reader.next_event() {
+        let value = match event {
+            Event::StartObject => {
+                stack.push((JsonData::Data(HashMap::new()), None));
+                continue;
+            }
+            Event::StartArray => {
+                stack.push((JsonData::Arr(Vec::new()), None));
+                continue;
+            }
+            Event::Key(key) => {
+                if let Some((_, name)) = stack.last_mut() {
+                    *name = Some(key.to_string())
+                }
+                continue;
+            }
+            Event::EndObject | Event::EndArray => match stack.pop() {
+                Some((container, _)) => container.clone(),
+                None => return JsonData::Err("unbalanced container".to_string()),
+            },
+            Event::String(text) => JsonData::Text(text.to_string()),
+            Event::Number(num) => JsonData::Num(num),
+            Event::Bool(b) => JsonData::Bool(b),
+            Event::Null => JsonData::Null,
+            Event::Err(err) => return JsonData::Err(err),
+        };
+        match stack.last_mut() {
+            Some((JsonData::Arr(arr), _)) => arr.push(value),
+            Some((JsonData::Data(obj), name)) => {
+                obj.insert(name.take().unwrap_or_default(), value);
+            }
+            Some(_) => unreachable!(),
+            None => return value,
+        }
+    }
+    JsonData::None
+}
+
+/// Returns the best time of several rounds
+fn measure(parse: impl Fn() -> JsonData) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            let res = parse();
+            let time = start.elapsed();
+            assert!(!matches!(res, JsonData::Err(_) | JsonData::None), "{res:?}");
+            time
+        })
+        .min()
+        .unwrap()
+}
+
+/// Compares building and dropping of `JsonData` and `JsonDoc`
+fn arena() {
+    println!("JsonData vs JsonDoc, parse and drop");
+    let record = r#"{"name":"Malvika","age":19,"tags":["a","b"],"address":{"city":"Delhi","zip":"110001"}}"#;
+    let json = format!("[{}]", vec![record; 50_000].join(","));
+    let options = ParseOptions::default();
+    let data = measure(|| {
+        drop(simjson::parse_with(&json, &options));
+        JsonData::Null
+    });
+    let doc = measure(|| match simjson::JsonDoc::parse_with(&json, &options) {
+        Ok(doc) => {
+            drop(doc);
+            JsonData::Null
+        }
+        Err(err) => JsonData::Err(err),
+    });
+    println!(
+        "{:>10} bytes {:>10.3} ms JsonData {:>10.3} ms JsonDoc",
+        json.len(),
+        data.as_secs_f64() * 1e3,
+        doc.as_secs_f64() * 1e3
+    );
+    println!("JsonDoc key interning");
+    for intern_keys in [false, true] {
+        let parse = match intern_keys {
+            true => simjson::JsonDoc::parse_interned,
+            false => simjson::JsonDoc::parse_with,
+        };
+        let options = ParseOptions::default();
+        let time = measure(|| result(parse(&json, &options)));
+        let doc = parse(&json, &options).unwrap();
+        println!(
+            "{:>10} {:>10.3} ms {:>10} bytes allocated",
+            if intern_keys { "interned" } else { "plain" },
+            time.as_secs_f64() * 1e3,
+            doc.allocated()
+        )
+    }
+}
+
+/// A xorshift generator keeping the corpora the same from run to run
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % 1_000_000_007
+    }
+}
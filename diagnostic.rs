@@ -0,0 +1,194 @@
+//! Parsing errors located in the text
+use std::fmt;
+
+use crate::{Builder, Frame, JsonData, JsonState, Lexer, ParseOptions, Position, Tok};
+
+/// Chars of a long line shown around the error position
+const CONTEXT: usize = 60;
+
+/// A parsing error with its position, JSON Pointer of the value
+/// containing it and the line of the text where it is
+///
+/// It's displayed as the message followed by the line and a caret under
+/// the error position.
+/// ```
+/// let err = simjson::try_parse("{\"a\": [1, 2 3]}").unwrap_err();
+/// assert_eq!(err.message, "expected ',' or ']' after array element, found '3'");
+/// assert_eq!((err.position.line, err.position.column), (1, 13));
+/// assert_eq!(err.pointer, "/a");
+/// assert_eq!(err.to_string(), "\
+/// error: expected ',' or ']' after array element, found '3'
+///  --> line 1, column 13, in /a
+///   |
+/// 1 | {\"a\": [1, 2 3]}
+///   |             ^
+/// ");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// What is wrong, the same as in [JsonData::Err] without the position
+    pub message: String,
+    pub position: Position,
+    /// JSON Pointer of the innermost value containing the error, empty for
+    /// the top level value
+    pub pointer: String,
+    /// The line of the error, a long one is cut around the position
+    excerpt: String,
+    /// Chars of the excerpt before the position
+    caret: usize,
+}
+
+/// Parses a `&str` to `JsonData` or returns [Diagnostic] of an error
+pub fn try_parse(json: &str) -> Result<JsonData, Diagnostic> {
+    try_parse_with(json, &ParseOptions::default())
+}
+
+/// Parses a `&str` applying the specified options, as [try_parse]
+pub fn try_parse_with(json: &str, options: &ParseOptions) -> Result<JsonData, Diagnostic> {
+    let mut lexer = Lexer::with_options(options);
+    // a repeated key is kept aside while the containers are still open
+    let mut builder = Builder {
+        errors: Some(Vec::new()),
+        ..Default::default()
+    };
+    let mut at = 0;
+    while at < json.len() {
+        for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+            if let Some(value) = feed(tok, &mut lexer, &mut builder, json)? {
+                return Ok(value);
+            }
+        }
+    }
+    match lexer.finish() {
+        Some(tok) => Ok(feed(tok, &mut lexer, &mut builder, json)?.unwrap_or(JsonData::None)),
+        None => Ok(JsonData::None),
+    }
+}
+
+fn feed(
+    tok: Tok,
+    lexer: &mut Lexer,
+    builder: &mut Builder,
+    json: &str,
+) -> Result<Option<JsonData>, Diagnostic> {
+    if !matches!(tok, Tok::Err(_)) {
+        let value = builder.token(tok, lexer);
+        // an error of a next token is reported when the token comes
+        if builder.errors.as_ref().is_none_or(Vec::is_empty) {
+            return Ok(value);
+        }
+    }
+    let fault = lexer.fault.take().expect("an error is recorded");
+    Err(Diagnostic::new(
+        json,
+        fault.what,
+        fault.at,
+        pointer(&builder.stack, fault.state),
+    ))
+}
+
+/// Returns JSON Pointer of the open containers, along with the member
+/// being parsed when the error is inside its value
+fn pointer(stack: &[Frame], state: JsonState) -> String {
+    // a value is expected after a colon in the start state
+    let inside = !matches!(
+        state,
+        JsonState::ObjState
+            | JsonState::ObjName
+            | JsonState::ObjIdent
+            | JsonState::EscName
+            | JsonState::UniDigName
+            | JsonState::ExpNameSep
+            | JsonState::ObjExpEnd
+            | JsonState::ArrState
+            | JsonState::ArrNext
+    );
+    let depth = if inside {
+        stack.len()
+    } else {
+        stack.len().saturating_sub(1)
+    };
+    path(&stack[..depth])
+}
+
+/// Returns JSON Pointer of a next value of the innermost container
+pub(crate) fn path(stack: &[Frame]) -> String {
+    let mut pointer = String::new();
+    for frame in stack {
+        push_component(&mut pointer, frame)
+    }
+    pointer
+}
+
+/// Appends a component of a next value of the container to JSON Pointer
+pub(crate) fn push_component(pointer: &mut String, frame: &Frame) {
+    pointer.push('/');
+    match frame {
+        Frame::Arr(arr) => pointer.push_str(&arr.len().to_string()),
+        Frame::Obj(members) => pointer.push_str(&members.key.replace('~', "~0").replace('/', "~1")),
+    }
+}
+
+impl Diagnostic {
+    fn new(json: &str, message: String, position: Position, pointer: String) -> Self {
+        let offset = position.offset.min(json.len());
+        let start = json[..offset].rfind('\n').map_or(0, |nl| nl + 1);
+        let end = json[offset..]
+            .find('\n')
+            .map_or(json.len(), |nl| offset + nl);
+        let before: Vec<char> = json[start..offset].chars().collect();
+        let after = json[offset..end].trim_end_matches('\r');
+        let mut excerpt = String::new();
+        if before.len() > CONTEXT {
+            excerpt.push_str("...")
+        }
+        excerpt.extend(&before[before.len().saturating_sub(CONTEXT)..]);
+        let caret = excerpt.chars().count();
+        excerpt.extend(after.chars().take(CONTEXT));
+        if after.chars().nth(CONTEXT).is_some() {
+            excerpt.push_str("...")
+        }
+        Diagnostic {
+            message,
+            position,
+            pointer,
+            excerpt,
+            caret,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = self.position.line.to_string();
+        let gutter = " ".repeat(line.len());
+        write!(
+            f,
+            "error: {}\n{gutter}--> line {line}, column {}",
+            self.message, self.position.column
+        )?;
+        if !self.pointer.is_empty() {
+            f.write_str(", in ")?;
+            for c in self.pointer.chars() {
+                match c.is_control() {
+                    true => write!(f, "{}", c.escape_default())?,
+                    false => write!(f, "{c}")?,
+                }
+            }
+        }
+        // tabs stay in the padding, so the caret lines up with the text
+        let pad: String = self
+            .excerpt
+            .chars()
+            .take(self.caret)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(
+            f,
+            "\n{gutter} |\n{line} | {}\n{gutter} | {pad}^",
+            self.excerpt
+        )
+    }
+}
+
+impl std::error::Error for Diagnostic {}
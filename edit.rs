@@ -0,0 +1,392 @@
+//! Editing of JSON text keeping its comments and formatting
+use std::{borrow::Cow, fmt, ops::Range};
+
+use crate::{
+    DuplicateKeys, JsonData, ParseOptions,
+    lazy::{self, Kind, Node, View},
+};
+
+/// JSON or JSONC text edited by slash separated paths
+///
+/// Values are located on a tape recorded by the tokenizer as in
+/// [crate::LazyJson], an edit rewrites only bytes of the changed member,
+/// so comments, whitespace and key order elsewhere stay byte-identical.
+/// A new member follows the indentation of its siblings and its value is
+/// written compact.
+/// ```
+/// let options = simjson::ParseOptions { jsonc: true, ..Default::default() };
+/// let mut doc = simjson::JsonEdit::with_options("{\n  // web\n  \"port\": 80\n}", &options).unwrap();
+/// doc.set(&"port", &simjson::JsonData::Num(8080.0)).unwrap();
+/// doc.set(&"host", &simjson::JsonData::Text("localhost".to_string())).unwrap();
+/// assert_eq!(doc.to_string(), "{\n  // web\n  \"port\": 8080,\n  \"host\": \"localhost\"\n}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonEdit {
+    text: String,
+    tape: Vec<Node>,
+    options: ParseOptions,
+}
+
+impl JsonEdit {
+    pub fn new(json: &str) -> Result<Self, String> {
+        Self::with_options(json, &ParseOptions::default())
+    }
+
+    pub fn with_options(json: &str, options: &ParseOptions) -> Result<Self, String> {
+        Ok(JsonEdit {
+            text: json.to_string(),
+            tape: lazy::index(json, options)?,
+            options: options.clone(),
+        })
+    }
+
+    /// Returns the edited text
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns a value of the path, same as [crate::LazyJson::get]
+    pub fn get(&self, path: &impl AsRef<str>) -> Option<JsonData> {
+        Some(crate::parse_with(self.raw(path)?, &self.options))
+    }
+
+    /// Returns the source text of a value of the path
+    pub fn raw(&self, path: &impl AsRef<str>) -> Option<&str> {
+        let node = self
+            .view()
+            .find(path.as_ref().split('/').map(Cow::Borrowed))?;
+        Some(self.view().slice(node))
+    }
+
+    /// Returns a value addressed by JSON Pointer (RFC 6901), keys
+    /// containing `/` are reachable only this way
+    pub fn pointer(&self, pointer: &str) -> Option<JsonData> {
+        let node = match pointer {
+            "" => self.tape.first().map(|_| 0)?,
+            _ => self.view().find(lazy::pointer_comps(pointer)?)?,
+        };
+        Some(crate::parse_with(self.view().slice(node), &self.options))
+    }
+
+    /// Replaces a value of the path, or adds a member to an object or an
+    /// element at the end of an array when the path doesn't exist yet
+    ///
+    /// A value holding [JsonData::None] or [JsonData::Err] is an error.
+    pub fn set(&mut self, path: &impl AsRef<str>, value: &JsonData) -> Result<(), String> {
+        let path = path.as_ref();
+        self.set_at(path.split('/').map(Cow::Borrowed).collect(), path, value)
+    }
+
+    /// Sets a value addressed by JSON Pointer, as [JsonEdit::set]
+    pub fn set_pointer(&mut self, pointer: &str, value: &JsonData) -> Result<(), String> {
+        match lazy::pointer_comps(pointer) {
+            Some(comps) => self.set_at(comps.collect(), pointer, value),
+            None => Err(format!("an error: invalid pointer {pointer}")),
+        }
+    }
+
+    /// Removes a member of an object or an element of an array and returns
+    /// its value, comments around it are kept
+    pub fn remove(&mut self, path: &impl AsRef<str>) -> Option<JsonData> {
+        self.remove_at(path.as_ref().split('/').map(Cow::Borrowed).collect())
+    }
+
+    /// Removes a value addressed by JSON Pointer, as [JsonEdit::remove]
+    pub fn remove_pointer(&mut self, pointer: &str) -> Option<JsonData> {
+        self.remove_at(lazy::pointer_comps(pointer)?.collect())
+    }
+
+    fn set_at(
+        &mut self,
+        mut comps: Vec<Cow<str>>,
+        path: &str,
+        value: &JsonData,
+    ) -> Result<(), String> {
+        if !writable(value) {
+            return Err(format!("an error: a value to set at {path} holds None or Err"));
+        }
+        let name = comps.pop().unwrap_or_default();
+        let parent = self.parent(comps, path)?;
+        let members = self.members(parent);
+        let value = crate::to_json(value);
+        if let Some(inx) = self.position(parent, &members, &name) {
+            let Node { start, end, .. } = self.tape[members[inx].1];
+            return self.apply(vec![(start..end, value)]);
+        }
+        let entry = match self.tape[parent].kind {
+            Kind::Obj => {
+                let key = crate::to_json(&JsonData::Text(name.into_owned()));
+                format!("{key}{}{value}", self.separator(&members))
+            }
+            _ if name.parse() == Ok(members.len()) => value,
+            _ => {
+                return Err(format!(
+                    "an error: index {name} is out of {} elements of {path}",
+                    members.len()
+                ));
+            }
+        };
+        let edits = self.append(parent, &members, entry);
+        self.apply(edits)
+    }
+
+    fn remove_at(&mut self, mut comps: Vec<Cow<str>>) -> Option<JsonData> {
+        let name = comps.pop()?;
+        let parent = self.parent(comps, "").ok()?;
+        let members = self.members(parent);
+        let inx = self.position(parent, &members, &name)?;
+        let value = crate::parse_with(self.view().slice(members[inx].1), &self.options);
+        let (start, end) = self.span(members[inx]);
+        let after = self.skip_blank(end);
+        let edits = if self.text[after..].starts_with(',') {
+            // a comment ending the line goes along with the member, and so
+            // does the whole line when the member starts it
+            let range = match (self.comment_end(after + 1), self.indent(start)) {
+                (Some(eol), Some(indent)) => start - indent.len()..self.skip_line_break(eol),
+                (Some(eol), None) => start..eol,
+                _ => start..self.skip_space(after + 1),
+            };
+            vec![(range, String::new())]
+        } else {
+            let mut edits = Vec::new();
+            let mut blank = self.tape[parent].start + 1;
+            if inx > 0 {
+                let comma = self.skip_blank(self.span(members[inx - 1]).1);
+                edits.push((comma..comma + 1, String::new()));
+                blank = comma + 1
+            }
+            let from = self.space_start(blank, start);
+            let end = self.comment_end(end).unwrap_or(end);
+            // the member is on its own line after a comment, the line goes
+            let end = match self.text[..from].ends_with('\n') {
+                true => self.skip_line_break(end),
+                false => end,
+            };
+            edits.push((from..end, String::new()));
+            edits
+        };
+        let applied = self.apply(edits);
+        debug_assert!(applied.is_ok(), "{applied:?}");
+        applied.ok()?;
+        Some(value)
+    }
+
+    fn view(&self) -> View<'_, '_> {
+        View {
+            json: &self.text,
+            tape: &self.tape,
+            options: &self.options,
+        }
+    }
+
+    /// Finds a container of the path components
+    fn parent(&self, comps: Vec<Cow<str>>, path: &str) -> Result<usize, String> {
+        match self.view().find(comps.into_iter()) {
+            Some(node) if matches!(self.tape[node].kind, Kind::Obj | Kind::Arr) => Ok(node),
+            _ => Err(format!("an error: no object or array holds {path}")),
+        }
+    }
+
+    /// Returns tape indices of the first node and of the value of every
+    /// member of a container, they are same for an array element
+    fn members(&self, parent: usize) -> Vec<(usize, usize)> {
+        let node = self.tape[parent];
+        let mut members = Vec::new();
+        let mut inx = parent + 1;
+        while inx < node.next {
+            let value = if node.kind == Kind::Obj { inx + 1 } else { inx };
+            members.push((inx, value));
+            inx = self.tape[value].next
+        }
+        members
+    }
+
+    fn position(&self, parent: usize, members: &[(usize, usize)], name: &str) -> Option<usize> {
+        match self.tape[parent].kind {
+            Kind::Obj if self.options.duplicate_keys == DuplicateKeys::LastWins => members
+                .iter()
+                .rposition(|&(key, _)| self.view().is_key(key, name)),
+            Kind::Obj => members
+                .iter()
+                .position(|&(key, _)| self.view().is_key(key, name)),
+            _ => name.parse().ok().filter(|&inx| inx < members.len()),
+        }
+    }
+
+    fn span(&self, (first, value): (usize, usize)) -> (usize, usize) {
+        (self.tape[first].start, self.tape[value].end)
+    }
+
+    /// Returns what separates keys from values in the object, `: ` when
+    /// it is empty or a comment is in between
+    fn separator(&self, members: &[(usize, usize)]) -> &str {
+        members
+            .first()
+            .map(|&(key, value)| &self.text[self.tape[key].end..self.tape[value].start])
+            .filter(|sep| !sep.contains('/'))
+            .unwrap_or(": ")
+    }
+
+    /// Returns edits adding an entry after the last member, on its own line
+    /// when the last member is on its own line
+    fn append(
+        &self,
+        parent: usize,
+        members: &[(usize, usize)],
+        entry: String,
+    ) -> Vec<(Range<usize>, String)> {
+        let newline = if self.text.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let Some(&last) = members.last() else {
+            let open = self.tape[parent].start + 1;
+            let Node { start, end, .. } = self.tape[parent];
+            if !self.text[start..end].contains('\n') {
+                return vec![(open..open, entry)];
+            }
+            let line = &self.text[self.text[..start].rfind('\n').map_or(0, |nl| nl + 1)..start];
+            let indent = format!(
+                "{}{}",
+                &line[..line.len() - line.trim_start().len()],
+                self.indent_unit()
+            );
+            return vec![(open..open, format!("{newline}{indent}{entry}"))];
+        };
+        let (start, end) = self.span(last);
+        let after = self.skip_blank(end);
+        let trailing = self.text[after..].starts_with(',');
+        let eol = self.line_end(if trailing { after + 1 } else { end });
+        match (self.indent(start), eol) {
+            (Some(indent), Some(eol)) if trailing => {
+                vec![(eol..eol, format!("{newline}{indent}{entry},"))]
+            }
+            (Some(indent), Some(eol)) => vec![
+                (end..end, ",".to_string()),
+                (eol..eol, format!("{newline}{indent}{entry}")),
+            ],
+            _ if trailing => vec![(after + 1..after + 1, format!(" {entry},"))],
+            _ => vec![(end..end, format!(", {entry}"))],
+        }
+    }
+
+    /// Returns leading whitespace of a line when the position is the first
+    /// non-blank char of it
+    fn indent(&self, at: usize) -> Option<&str> {
+        let line = self.text[..at].rfind('\n').map_or(0, |nl| nl + 1);
+        let indent = &self.text[line..at];
+        indent
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+            .then_some(indent)
+    }
+
+    /// Returns the indentation of the first indented line, two spaces by default
+    fn indent_unit(&self) -> &str {
+        self.text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| &line[..line.len() - line.trim_start().len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ")
+    }
+
+    /// Returns where a line ends when only spaces and a line comment follow
+    /// the position
+    fn line_end(&self, at: usize) -> Option<usize> {
+        let rest = &self.text[at..];
+        let code = rest.trim_start_matches([' ', '\t']);
+        let eol = if code.starts_with("//") {
+            code.find(['\r', '\n'])?
+        } else if code.starts_with(['\r', '\n']) {
+            0
+        } else {
+            return None;
+        };
+        Some(at + rest.len() - code.len() + eol)
+    }
+
+    /// Returns where a line ends when a line comment follows the position
+    fn comment_end(&self, at: usize) -> Option<usize> {
+        self.line_end(at)
+            .filter(|&eol| self.text[at..eol].trim_start().starts_with("//"))
+    }
+
+    /// Returns where whitespace ending the blank text between the positions
+    /// starts, a line break closing a line comment is kept
+    fn space_start(&self, mut at: usize, end: usize) -> usize {
+        let mut start = at;
+        loop {
+            at = self.skip_space(at).min(end);
+            let rest = &self.text[at..end];
+            at += if rest.starts_with("//") {
+                rest.find('\n').map_or(rest.len(), |nl| nl + 1)
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                comment.find("*/").map_or(rest.len(), |end| end + 4)
+            } else {
+                return start;
+            };
+            start = at
+        }
+    }
+
+    /// Skips spaces and a line break following them
+    fn skip_line_break(&self, at: usize) -> usize {
+        let rest = self.text[at..].trim_start_matches([' ', '\t']);
+        match rest.strip_prefix("\r\n").or(rest.strip_prefix('\n')) {
+            Some(next) => self.text.len() - next.len(),
+            None => at,
+        }
+    }
+
+    fn skip_space(&self, at: usize) -> usize {
+        self.text.len()
+            - self.text[at..]
+                .trim_start_matches([' ', '\t', '\r', '\n'])
+                .len()
+    }
+
+    /// Skips whitespace and comments
+    fn skip_blank(&self, mut at: usize) -> usize {
+        loop {
+            at = self.skip_space(at);
+            let rest = &self.text[at..];
+            at += if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                comment.find("*/").map_or(rest.len(), |end| end + 4)
+            } else {
+                return at;
+            }
+        }
+    }
+
+    /// Applies edits ordered by position and indexes the text again
+    fn apply(&mut self, edits: Vec<(Range<usize>, String)>) -> Result<(), String> {
+        let mut text = self.text.clone();
+        for (range, replace) in edits.into_iter().rev() {
+            text.replace_range(range, &replace)
+        }
+        self.tape = lazy::index(&text, &self.options)?;
+        self.text = text;
+        Ok(())
+    }
+}
+
+/// Tells if the value has JSON text, [JsonData::None] and [JsonData::Err]
+/// have none
+fn writable(value: &JsonData) -> bool {
+    match value {
+        JsonData::Data(obj) => obj.values().all(writable),
+        JsonData::Arr(arr) => arr.iter().all(writable),
+        JsonData::None | JsonData::Err(_) => false,
+        _ => true,
+    }
+}
+
+impl fmt::Display for JsonEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
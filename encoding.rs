@@ -0,0 +1,115 @@
+//! Decoding of JSON bytes in any Unicode encoding
+use std::borrow::Cow;
+
+use crate::{JsonData, ParseOptions};
+
+/// A Unicode encoding of JSON bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+/// Parses JSON bytes in UTF-8, UTF-16 or UTF-32, see [decode]
+/// ```
+/// let utf16: Vec<u8> = "[\"é\"]".encode_utf16().flat_map(u16::to_le_bytes).collect();
+/// assert_eq!(simjson::parse_bytes(&utf16), simjson::parse("[\"é\"]"));
+/// assert_eq!(simjson::parse_bytes(b"\xEF\xBB\xBF{}"), simjson::parse("{}"));
+/// ```
+pub fn parse_bytes(bytes: &[u8]) -> JsonData {
+    parse_bytes_with(bytes, &ParseOptions::default())
+}
+
+/// Parses JSON bytes applying the specified options
+pub fn parse_bytes_with(bytes: &[u8], options: &ParseOptions) -> JsonData {
+    match decode(bytes) {
+        Ok(json) => crate::parse_with(&json, options),
+        Err(err) => JsonData::Err(err),
+    }
+}
+
+/// Decodes JSON bytes to text dropping a byte order mark
+///
+/// The encoding is given by the byte order mark, and without it by zeros
+/// among the first four bytes as RFC 4627 suggests, since the first two
+/// chars of JSON text are ASCII. UTF-8 text is borrowed, other encodings
+/// are converted.
+pub fn decode(bytes: &[u8]) -> Result<Cow<'_, str>, String> {
+    let (encoding, bom) = detect(bytes);
+    let bytes = &bytes[bom..];
+    let width = match encoding {
+        Encoding::Utf8 => {
+            return std::str::from_utf8(bytes)
+                .map(Cow::Borrowed)
+                .map_err(|err| {
+                    format!(
+                        "an error: invalid UTF-8 at byte {}",
+                        bom + err.valid_up_to()
+                    )
+                });
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => 2,
+        Encoding::Utf32Le | Encoding::Utf32Be => 4,
+    };
+    let name = if width == 2 { "UTF-16" } else { "UTF-32" };
+    let units = bytes
+        .chunks(width)
+        .map(|unit| match (unit.len() == width, encoding) {
+            (false, _) => None,
+            (_, Encoding::Utf16Le) => Some(u16::from_le_bytes([unit[0], unit[1]]) as u32),
+            (_, Encoding::Utf16Be) => Some(u16::from_be_bytes([unit[0], unit[1]]) as u32),
+            (_, Encoding::Utf32Le) => {
+                Some(u32::from_le_bytes([unit[0], unit[1], unit[2], unit[3]]))
+            }
+            (_, _) => Some(u32::from_be_bytes([unit[0], unit[1], unit[2], unit[3]])),
+        });
+    let mut text = String::with_capacity(bytes.len() / width);
+    let mut high = None;
+    for (inx, unit) in units.enumerate() {
+        let invalid = || format!("an error: invalid {name} at byte {}", bom + inx * width);
+        let unit = unit.ok_or_else(invalid)?;
+        let code = match (high.take(), unit) {
+            (None, 0xD800..0xDC00) if width == 2 => {
+                high = Some(unit);
+                continue;
+            }
+            (Some(high), 0xDC00..0xE000) => 0x10000 + ((high - 0xD800) << 10) + (unit - 0xDC00),
+            (Some(_), _) => {
+                return Err(format!(
+                    "an error: invalid {name} at byte {}",
+                    bom + (inx - 1) * width
+                ));
+            }
+            (None, unit) => unit,
+        };
+        text.push(char::from_u32(code).ok_or_else(invalid)?)
+    }
+    if high.is_some() {
+        return Err(format!(
+            "an error: invalid {name} at byte {}",
+            bom + bytes.len() - width
+        ));
+    }
+    Ok(Cow::Owned(text))
+}
+
+/// Returns the encoding of bytes and the length of their byte order mark
+fn detect(bytes: &[u8]) -> (Encoding, usize) {
+    let zero = |inx: usize| bytes.get(inx) == Some(&0);
+    let some = |inx: usize| bytes.get(inx).is_some_and(|&b| b != 0);
+    match bytes {
+        [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3),
+        [0, 0, 0xFE, 0xFF, ..] => (Encoding::Utf32Be, 4),
+        [0xFF, 0xFE, 0, 0, ..] => (Encoding::Utf32Le, 4),
+        [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2),
+        [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2),
+        _ if zero(0) && zero(1) && zero(2) && some(3) => (Encoding::Utf32Be, 0),
+        _ if some(0) && zero(1) && zero(2) && zero(3) => (Encoding::Utf32Le, 0),
+        _ if zero(0) && some(1) => (Encoding::Utf16Be, 0),
+        _ if some(0) && zero(1) => (Encoding::Utf16Le, 0),
+        _ => (Encoding::Utf8, 0),
+    }
+}
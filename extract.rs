@@ -0,0 +1,197 @@
+//! Extraction of selected values from JSON text without parsing the rest
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::{DuplicateKeys, JsonData, Lexer, ParseOptions, Position, Tok};
+
+/// A component of the current location, a key of a member or an index
+/// of an element being parsed
+enum Comp<'a> {
+    Key(Cow<'a, str>),
+    Index(usize),
+}
+
+/// Returns values of the slash separated paths in the same order, `None`
+/// stands for a path which doesn't exist
+///
+/// The text is streamed once, subtrees not leading to any of the paths are
+/// skipped without tracking their keys or allocating, and the scan stops
+/// as soon as all the values are found and no later key can replace them.
+/// Array elements are addressed by index.
+/// ```
+/// let line = r#"{"user":{"id":"a12","name":"Malvika"},"payload":[1,2,3],"meta":{"version":3}}"#;
+/// let values = simjson::extract(line, &["user/id", "meta/version", "meta/date"]);
+/// assert_eq!(values, [Some(simjson::JsonData::Text("a12".into())), Some(simjson::JsonData::Num(3.0)), None]);
+/// ```
+/// A syntax error stops the scan and the values found before are returned.
+pub fn extract(json: &str, paths: &[&str]) -> Vec<Option<JsonData>> {
+    extract_with(json, paths, &ParseOptions::default())
+}
+
+/// Returns values of the paths parsing with the options
+///
+/// A repeated key resolves as in [crate::LazyJson]: the last value wins by
+/// default, so the scan goes on until objects on the paths are closed.
+/// With [DuplicateKeys::Error] keys of all objects are checked, and
+/// a repeated one gives the error for every path.
+/// ```
+/// use simjson::{DuplicateKeys, JsonData, ParseOptions};
+/// assert_eq!(simjson::extract(r#"{"a":1,"a":2}"#, &["a"]), [Some(JsonData::Num(2.0))]);
+/// let options = ParseOptions { duplicate_keys: DuplicateKeys::Error, ..Default::default() };
+/// assert!(matches!(simjson::extract_with(r#"{"a":1,"a":2}"#, &["a"], &options)[0], Some(JsonData::Err(_))));
+/// ```
+pub fn extract_with(json: &str, paths: &[&str], options: &ParseOptions) -> Vec<Option<JsonData>> {
+    let paths: Vec<Vec<&str>> = paths.iter().map(|path| path.split('/').collect()).collect();
+    let mut found = vec![None; paths.len()];
+    // strings without escapes are borrowed, the rest of an escaped one is copied
+    let mut lexer = Lexer {
+        borrow: true,
+        ..Lexer::with_options(options)
+    };
+    let policy = options.duplicate_keys;
+    // a depth down to which a path was entered, and if a repeated key
+    // leaves the path with the first value found or not
+    let mut entered = vec![0; paths.len()];
+    let mut settled = vec![false; paths.len()];
+    // starts of keys of every open container, kept for the error policy only
+    let mut keys: Option<Vec<HashMap<String, Position>>> = (policy == DuplicateKeys::Error).then(Vec::new);
+    let mut location: Vec<Comp> = Vec::new();
+    let mut captures: Vec<(usize, usize, usize)> = Vec::new();
+    let mut skip = 0;
+    let mut open = 0;
+    let mut at = 0;
+    let done = |found: &[Option<JsonData>], settled: &[bool], location: &[Comp]| {
+        found.iter().zip(settled).all(|(found, &settled)| found.is_some() || settled)
+            && match policy {
+                DuplicateKeys::Error => false,
+                DuplicateKeys::LastWins => !location.iter().any(|comp| matches!(comp, Comp::Key(_))),
+                _ => true,
+            }
+    };
+    while at < json.len() && !done(&found, &settled, &location) {
+        for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+            let start = lexer.position(&tok).offset;
+            let end = match tok {
+                Tok::Num(_) => lexer.last.offset,
+                _ => lexer.last.offset + 1,
+            };
+            let key = match tok {
+                Tok::Key if skip == 0 || keys.is_some() => Some(lexer.take_text(json)),
+                Tok::Key | Tok::Str => {
+                    lexer.span = None;
+                    None
+                }
+                _ => None,
+            };
+            if let Some(keys) = &mut keys {
+                match tok {
+                    Tok::StartObject | Tok::StartArray => keys.push(HashMap::new()),
+                    Tok::EndObject | Tok::EndArray => _ = keys.pop(),
+                    _ => (),
+                }
+                if let (Some(key), Some(starts)) = (&key, keys.last_mut()) {
+                    if let Some(&first) = starts.get(key.as_ref()) {
+                        let err = lexer.duplicate(key, first);
+                        return vec![Some(JsonData::Err(err)); paths.len()];
+                    }
+                    starts.insert(key.to_string(), lexer.mark);
+                }
+            }
+            match tok {
+                Tok::Err(_) => return found,
+                _ if skip > 0 => match tok {
+                    Tok::StartObject | Tok::StartArray => skip += 1,
+                    Tok::EndObject | Tok::EndArray => {
+                        skip -= 1;
+                        if skip == 0 {
+                            complete(json, options, &mut found, &mut captures, location.len(), end)
+                        }
+                    }
+                    _ => (),
+                },
+                Tok::Key => {
+                    if let (Some(Comp::Key(cur)), Some(key)) = (location.last_mut(), key) {
+                        *cur = key
+                    }
+                    // a repeated key replaces values found under the earlier one,
+                    // or is ignored when the first one wins
+                    for (inx, path) in paths.iter().enumerate() {
+                        if !on_path(&location, path) {
+                            continue;
+                        }
+                        if policy == DuplicateKeys::LastWins {
+                            found[inx] = None
+                        } else if entered[inx] >= location.len() {
+                            settled[inx] = true
+                        } else {
+                            entered[inx] = location.len()
+                        }
+                    }
+                }
+                Tok::EndObject | Tok::EndArray => {
+                    location.pop();
+                    complete(json, options, &mut found, &mut captures, location.len(), end)
+                }
+                _ => {
+                    if let Some(Comp::Index(index)) = location.last_mut() {
+                        *index = index.wrapping_add(1)
+                    }
+                    let mut descend = false;
+                    for (inx, path) in paths.iter().enumerate() {
+                        if found[inx].is_some() || settled[inx] || !on_path(&location, path) {
+                            continue;
+                        }
+                        if path.len() > location.len() {
+                            descend = true
+                        } else if matches!(tok, Tok::StartObject | Tok::StartArray) {
+                            captures.push((inx, start, location.len()))
+                        } else {
+                            found[inx] = Some(crate::parse_with(&json[start..end], options))
+                        }
+                    }
+                    match tok {
+                        Tok::StartObject if descend => location.push(Comp::Key(Cow::Borrowed(""))),
+                        Tok::StartArray if descend => location.push(Comp::Index(usize::MAX)),
+                        Tok::StartObject | Tok::StartArray => skip = 1,
+                        _ => (),
+                    }
+                }
+            }
+            // a token after the root value may come along with its last one
+            match tok {
+                Tok::StartObject | Tok::StartArray => open += 1,
+                Tok::EndObject | Tok::EndArray => open -= 1,
+                _ => (),
+            }
+            if open == 0 {
+                return found;
+            }
+        }
+    }
+    found
+}
+
+/// Tells if the location is a path or leads to it
+fn on_path(location: &[Comp], path: &[&str]) -> bool {
+    path.len() >= location.len()
+        && location.iter().zip(path).all(|(comp, name)| match comp {
+            Comp::Key(key) => key == name,
+            Comp::Index(index) => name.parse() == Ok(*index),
+        })
+}
+
+/// Parses captured containers closed at the depth
+fn complete(
+    json: &str,
+    options: &ParseOptions,
+    found: &mut [Option<JsonData>],
+    captures: &mut Vec<(usize, usize, usize)>,
+    depth: usize,
+    end: usize,
+) {
+    captures.retain(|&(path, start, at_depth)| {
+        if at_depth == depth {
+            found[path] = Some(crate::parse_with(&json[start..end], options))
+        }
+        at_depth != depth
+    })
+}
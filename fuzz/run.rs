@@ -0,0 +1,153 @@
+//! Local fuzzer of simjson, no cargo-fuzz or nightly compiler needed
+//!
+//! Build the crate and the program in release mode with debug assertions,
+//! ```sh
+//! export VERSION=fuzz
+//! rustc -C opt-level=3 -C debug-assertions --edition 2024 --crate-type lib --crate-name simjson lib.rs
+//! rustc -C opt-level=3 -C debug-assertions --edition 2024 fuzz/run.rs --extern simjson=libsimjson.rlib
+//! ./run [ITERATIONS [SEED]]
+//! ```
+//! Inputs of `fuzz/regressions` are replayed through all targets first,
+//! then they are mutated for the given number of iterations. A panicking
+//! input is saved to `fuzz/artifacts` and the program exits with
+//! an error, add the input to the regressions after fixing.
+use std::{
+    collections::HashSet,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+#[path = "targets.rs"]
+mod targets;
+
+/// A message of the last panic
+static PANIC: Mutex<String> = Mutex::new(String::new());
+
+type Target = fn(&[u8]);
+
+const TARGETS: [(&str, Target); 5] = [
+    ("parse", targets::parse),
+    ("fragments", targets::fragments),
+    ("round_trip", targets::round_trip),
+    ("edit", targets::edit),
+    ("tolerant", targets::tolerant),
+];
+
+const REGRESSIONS: &str = "fuzz/regressions";
+const ARTIFACTS: &str = "fuzz/artifacts";
+
+/// Fragments of JSON syntax inserted by mutations
+const TOKENS: [&str; 26] = [
+    "{", "}", "[", "]", ",", ":", "\"", "\\", "\\u", "\\ud83d", "\\ude00", "true", "false", "null",
+    "-", "0", "1e", ".5", "E+", " ", "\n", "\"a\":", "é", "😀", "NaN", "Infinity",
+];
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let iterations: usize = args.next().map_or(100_000, |n| n.parse().expect("iterations"));
+    let seed: u64 = args.next().map_or(0x5EED_F00D, |n| n.parse().expect("seed"));
+    let mut corpus: Vec<Vec<u8>> = fs::read_dir(REGRESSIONS)
+        .map(|dir| {
+            dir.flatten()
+                .filter_map(|entry| fs::read(entry.path()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    corpus.push(Vec::new());
+    panic::set_hook(Box::new(|info| *PANIC.lock().unwrap() = info.to_string()));
+    let start = Instant::now();
+    let mut crashes = 0;
+    let mut seen = HashSet::new();
+    for input in &corpus {
+        crashes += run(input, &mut seen)
+    }
+    println!("{} regressions replayed, {crashes} crash(es)", corpus.len());
+    let mut random = Random(seed);
+    for _ in 0..iterations {
+        let input = mutate(&mut random, &corpus);
+        crashes += run(&input, &mut seen);
+        if corpus.len() < 10_000 && random.below(16) == 0 {
+            corpus.push(input)
+        }
+    }
+    println!(
+        "{iterations} inputs fuzzed in {:.1} s, {crashes} crash(es)",
+        start.elapsed().as_secs_f64()
+    );
+    if crashes > 0 {
+        std::process::exit(1)
+    }
+}
+
+/// Runs all targets and saves the input if some of them panics, only
+/// a first input panicking at the same place is reported
+fn run(input: &[u8], seen: &mut HashSet<String>) -> usize {
+    let mut crashes = 0;
+    for (name, target) in TARGETS {
+        if panic::catch_unwind(AssertUnwindSafe(|| target(input))).is_err() {
+            crashes += 1;
+            let message = std::mem::take(&mut *PANIC.lock().unwrap());
+            let place = message.lines().next().unwrap_or_default().to_string();
+            if !seen.insert(format!("{name} {place}")) {
+                continue;
+            }
+            let file = Path::new(ARTIFACTS).join(format!("{name}-{:016x}", hash(input)));
+            println!("{name} panics on {:?}, saved as {}", String::from_utf8_lossy(input), file.display());
+            println!("{message}");
+            fs::create_dir_all(ARTIFACTS)
+                .and_then(|_| fs::write(&file, input))
+                .unwrap_or_else(|err| println!("can't save {}: {err}", file.display()))
+        }
+    }
+    crashes
+}
+
+/// Changes a random input of the corpus by a few random edits
+fn mutate(random: &mut Random, corpus: &[Vec<u8>]) -> Vec<u8> {
+    let mut input = corpus[random.below(corpus.len())].clone();
+    for _ in 0..1 + random.below(4) {
+        let at = random.below(input.len() + 1);
+        match random.below(6) {
+            0 => input.splice(at..at, TOKENS[random.below(TOKENS.len())].bytes()).for_each(drop),
+            1 => input.insert(at, random.next() as u8),
+            2 if at < input.len() => input[at] ^= 1 << random.below(8),
+            3 => input.truncate(at),
+            4 => {
+                let end = (at + random.below(8)).min(input.len());
+                input.drain(at..end).for_each(drop)
+            }
+            _ => {
+                let other = &corpus[random.below(corpus.len())];
+                let from = random.below(other.len() + 1);
+                let end = (from + random.below(32)).min(other.len());
+                input.splice(at..at, other[from..end].iter().copied()).for_each(drop)
+            }
+        }
+    }
+    input.truncate(4096);
+    input
+}
+
+fn hash(input: &[u8]) -> u64 {
+    input
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
@@ -0,0 +1,321 @@
+//! Fuzzing targets shared by cargo-fuzz and the local runner
+//!
+//! Every target accepts arbitrary bytes, a target panics only when
+//! the library panics or when parsing APIs disagree on the same input.
+//! A cargo-fuzz target uses only one of them.
+#![allow(dead_code)]
+use simjson::{
+    DuplicateKeys, Event, JsonData, JsonDoc, JsonEdit, JsonParser, JsonReader, LazyJson, LoneSurrogates,
+    NonFinite, ParseOptions, Parsed, WriteOptions,
+};
+
+/// Splits the first byte off to pick parsing options, the rest is JSON
+/// text, invalid UTF-8 is replaced
+fn input(data: &[u8]) -> (ParseOptions, String) {
+    let Some((&first, rest)) = data.split_first() else {
+        return (ParseOptions::default(), String::new());
+    };
+    let mut options = match first & 3 {
+        0 => ParseOptions::default(),
+        1 => ParseOptions {
+            max_depth: (first >> 2) as usize % 5,
+            max_string_len: (first >> 4) as usize,
+            max_object_members: (first >> 3) as usize % 4,
+            max_array_elements: (first >> 2) as usize % 6,
+            max_number_len: (first >> 5) as usize,
+            ..Default::default()
+        },
+        2 => ParseOptions {
+            max_input_len: rest.len() / 2,
+            ..Default::default()
+        },
+        _ => ParseOptions {
+            max_depth: 10_000,
+            ..Default::default()
+        },
+    };
+    options.json5 = first & 0x40 != 0;
+    options.jsonc = first & 0x08 != 0;
+    if first & 0x80 != 0 {
+        options.duplicate_keys = [
+            DuplicateKeys::LastWins,
+            DuplicateKeys::FirstWins,
+            DuplicateKeys::Error,
+            DuplicateKeys::Collect,
+        ][(first >> 2) as usize & 3];
+        options.non_finite = first & 0x10 != 0;
+        options.lone_surrogates = [
+            LoneSurrogates::Replace,
+            LoneSurrogates::Error,
+            LoneSurrogates::Escape,
+        ][first as usize % 3]
+    }
+    (options, String::from_utf8_lossy(rest).into_owned())
+}
+
+/// Parses by every API and checks they agree
+pub fn parse(data: &[u8]) {
+    let (options, json) = input(data);
+    let value = simjson::parse_with(&json, &options);
+    same(
+        &simjson::parse_fragment_with(&mut json.chars(), &options).0,
+        &value,
+    );
+    same(
+        &simjson::parse_ref_with(&json, &options).into_owned(),
+        &value,
+    );
+    match simjson::try_parse_with(&json, &options) {
+        Ok(ok) => same(&ok, &value),
+        Err(err) => {
+            let at = format!("{}:{}", err.position.column, err.position.line);
+            assert!(
+                matches!(&value, JsonData::Err(text) if text.contains(&at)),
+                "{err}"
+            );
+            assert_eq!(err.to_string().lines().count(), 5)
+        }
+    }
+    for parse in [JsonDoc::parse_with, JsonDoc::parse_interned] {
+        match parse(&json, &options) {
+            Ok(doc) => same(&JsonData::from(&doc), &value),
+            Err(err) => assert_eq!(JsonData::Err(err), value),
+        }
+    }
+    let lazy = LazyJson::with_options(&json, &options);
+    assert_eq!(lazy.is_err(), matches!(value, JsonData::Err(_)));
+    let paths = ["0", "a", "a/0", "0/a/1", ""];
+    let extracted = simjson::extract_with(&json, &paths, &options);
+    if let Ok(lazy) = lazy {
+        same(&lazy.pointer("").unwrap_or(JsonData::None), &value);
+        for (path, extracted) in paths.into_iter().zip(&extracted) {
+            same(&lazy.get(&path).unwrap_or(JsonData::None), extracted.as_ref().unwrap_or(&JsonData::None));
+            lazy.raw(&path);
+            lazy.pointer(path);
+        }
+    }
+    simjson::extract(&json, &paths);
+    encodings(data, &json, &options, &value);
+    partial(data, &json, &options, &value);
+    let lines = simjson::parse_lines_with(&json, 3, &options);
+    let single = simjson::parse_lines_with(&json, 1, &options);
+    assert_eq!(lines.len(), single.len());
+    for ((line, value), (single_line, single_value)) in lines.iter().zip(&single) {
+        assert_eq!(line, single_line);
+        same(value, single_value)
+    }
+
+    let mut whole = JsonParser::with_options(&options);
+    let mut chunked = JsonParser::with_options(&options);
+    let mut values = (Vec::new(), Vec::new());
+    if let Parsed::Values(parsed) = whole.feed(&json) {
+        values.0 = parsed
+    }
+    let step = (data.len() % 7).max(1);
+    for chunk in json.chars().collect::<Vec<_>>().chunks(step) {
+        if let Parsed::Values(parsed) = chunked.feed(&chunk.iter().collect::<String>()) {
+            values.1.extend(parsed)
+        }
+    }
+    values.0.extend(whole.finish());
+    values.1.extend(chunked.finish());
+    same(&JsonData::Arr(values.0), &JsonData::Arr(values.1));
+
+    let mut reader = JsonReader::with_options(json.chars(), &options);
+    let mut count = 0;
+    while let Some((event, _)) = reader.next_event() {
+        count += 1;
+        let skip = matches!(event, Event::Key(_) | Event::StartArray) && count % 3 == 0;
+        let leave = matches!(event, Event::StartObject) && count % 4 == 0;
+        if skip {
+            reader.skip_value()
+        } else if leave {
+            reader.skip_container()
+        }
+        assert!(count <= json.len() + 1)
+    }
+}
+
+/// Parses the text and a prefix of it by `parse_partial`, a prefix of
+/// valid JSON is no error
+fn partial(data: &[u8], json: &str, options: &ParseOptions, value: &JsonData) {
+    let (whole, incomplete) = simjson::parse_partial_with(json, options);
+    match (&whole, value) {
+        (JsonData::Err(err), _) => {
+            assert!(matches!(value, JsonData::Err(_)), "{err}");
+            assert!(incomplete.is_empty())
+        }
+        (_, JsonData::Err(_)) => (),
+        _ => same(&whole, value),
+    }
+    let cut = data.len() * 7 % (json.chars().count() + 1);
+    let cut = json.char_indices().nth(cut).map_or(json.len(), |(at, _)| at);
+    let (prefix, _) = simjson::parse_partial_with(&json[..cut], options);
+    if let JsonData::Err(err) = prefix {
+        assert!(matches!(value, JsonData::Err(_)), "{err}")
+    }
+}
+
+/// Parses the text encoded in UTF-16 and UTF-32, with a byte order mark
+/// and without it when the encoding can be told by first two chars
+fn encodings(data: &[u8], json: &str, options: &ParseOptions, value: &JsonData) {
+    let _ = simjson::decode(data);
+    let ascii = json
+        .chars()
+        .take(2)
+        .all(|c| c.is_ascii() && c != '\0');
+    let utf16 = |le: bool| {
+        json.encode_utf16()
+            .flat_map(move |unit| if le { unit.to_le_bytes() } else { unit.to_be_bytes() })
+    };
+    let utf32 = |le: bool| {
+        json.chars().flat_map(move |c| {
+            if le {
+                (c as u32).to_le_bytes()
+            } else {
+                (c as u32).to_be_bytes()
+            }
+        })
+    };
+    let encoded: [(&[u8], Vec<u8>); 5] = [
+        (b"\xEF\xBB\xBF", json.as_bytes().to_vec()),
+        (b"\xFF\xFE", utf16(true).collect()),
+        (b"\xFE\xFF", utf16(false).collect()),
+        (b"\xFF\xFE\0\0", utf32(true).collect()),
+        (b"\0\0\xFE\xFF", utf32(false).collect()),
+    ];
+    for (bom, bytes) in encoded {
+        if !json.starts_with('\0') {
+            same(&simjson::parse_bytes_with(&[bom, &bytes].concat(), options), value)
+        }
+        if ascii {
+            same(&simjson::parse_bytes_with(&bytes, options), value)
+        }
+    }
+}
+
+/// Iterates over fragments until the input is exhausted
+pub fn fragments(data: &[u8]) {
+    let (options, json) = input(data);
+    let mut chars = json.chars();
+    for _ in 0..=json.len() {
+        match simjson::parse_fragment_with(&mut chars, &options).0 {
+            JsonData::None => return,
+            value => {
+                let text = simjson::to_json(&value);
+                assert!(!text.is_empty())
+            }
+        }
+    }
+    panic!("fragments of {json:?} don't end")
+}
+
+/// Checks that a parsed value is serialized to the text giving the same
+/// value, numbers out of the range are written as `null`
+pub fn round_trip(data: &[u8]) {
+    let (options, json) = input(data);
+    let value = simjson::parse_with(&json, &options);
+    if matches!(value, JsonData::Err(_) | JsonData::None) {
+        return;
+    }
+    let text = simjson::to_json(&value);
+    let options = ParseOptions {
+        max_depth: options.max_depth,
+        ..Default::default()
+    };
+    let again = simjson::parse_with(&text, &options);
+    if finite(&value) {
+        assert_eq!(again, value, "{text}")
+    }
+    let twice = simjson::to_json(&again);
+    assert_eq!(twice.len(), text.len());
+    assert_eq!(simjson::parse_with(&twice, &options), again);
+
+    let literal = WriteOptions {
+        non_finite: NonFinite::Literal,
+    };
+    let text = simjson::to_json_with(&value, &literal);
+    let options = ParseOptions {
+        non_finite: true,
+        ..options
+    };
+    same(&simjson::parse_with(&text, &options), &value)
+}
+
+/// Sets and removes values by paths and checks the edited text stays
+/// valid and gives the values set
+pub fn edit(data: &[u8]) {
+    let (options, json) = input(data);
+    let Ok(mut doc) = JsonEdit::with_options(&json, &options) else {
+        return;
+    };
+    for (inx, path) in ["a", "0", "a/0", "0/a", "", "1"].into_iter().enumerate() {
+        let value = match inx % 3 {
+            0 => JsonData::Num(inx as f64),
+            1 => JsonData::Text(path.to_string()),
+            _ => JsonData::Arr(vec![JsonData::Null]),
+        };
+        if doc.set(&path, &value).is_ok() {
+            let expected = simjson::parse_with(&simjson::to_json(&value), &options);
+            assert_eq!(doc.get(&path), Some(expected), "{}", doc.as_str())
+        }
+        if inx % 2 == 1 {
+            doc.remove(&path);
+        }
+        if let Err(err) = JsonEdit::with_options(doc.as_str(), &options) {
+            panic!("{err} in {}", doc.as_str())
+        }
+    }
+}
+
+/// Parses tolerating errors and checks a valid input gives the value of
+/// the strict parser and an invalid one gives errors, the strict parser
+/// ignores what follows the value
+pub fn tolerant(data: &[u8]) {
+    let (options, json) = input(data);
+    let (value, errors) = simjson::parse_tolerant_with(&json, &options);
+    assert!(errors.len() <= json.len() + 1);
+    match simjson::parse_with(&json, &options) {
+        JsonData::Err(err) => assert!(!errors.is_empty(), "{err} isn't reported"),
+        strict => {
+            same(&value, &strict);
+            assert!(
+                errors.iter().all(|err| err.contains("text after the value")
+                    || err.contains("unterminated comment")),
+                "{errors:?}"
+            )
+        }
+    }
+}
+
+/// Compares values taking NaN equal to itself
+fn same(left: &JsonData, right: &JsonData) {
+    fn eq(left: &JsonData, right: &JsonData) -> bool {
+        match (left, right) {
+            (JsonData::Num(left), JsonData::Num(right)) => {
+                left == right || left.is_nan() && right.is_nan()
+            }
+            (JsonData::Arr(left), JsonData::Arr(right)) => {
+                left.len() == right.len()
+                    && left.iter().zip(right).all(|(left, right)| eq(left, right))
+            }
+            (JsonData::Data(left), JsonData::Data(right)) => {
+                left.len() == right.len()
+                    && left
+                        .iter()
+                        .all(|(key, left)| right.get(key).is_some_and(|right| eq(left, right)))
+            }
+            _ => left == right,
+        }
+    }
+    assert!(eq(left, right), "{left:?} != {right:?}")
+}
+
+fn finite(value: &JsonData) -> bool {
+    match value {
+        JsonData::Num(num) => num.is_finite(),
+        JsonData::Arr(arr) => arr.iter().all(finite),
+        JsonData::Data(obj) => obj.values().all(finite),
+        _ => true,
+    }
+}
@@ -0,0 +1,260 @@
+//! Lazy JSON document materializing values on demand
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::{DuplicateKeys, JsonData, JsonRef, Lexer, ParseOptions, Position, Tok};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Obj,
+    Arr,
+    Key,
+    Value,
+}
+
+/// An entry of the tape, `start..end` is a byte range of the value in
+/// the input and `next` is an index of the entry following the value
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Node {
+    pub(crate) kind: Kind,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) next: usize,
+}
+
+/// JSON text indexed for a fast navigation
+///
+/// The input is scanned once to record positions of all values and keys
+/// on a tape, nothing is copied or allocated per value. A value is
+/// parsed only when it is requested, and lookups jump over siblings
+/// without looking inside them.
+/// ```
+/// let json = simjson::LazyJson::new(r#"{"user":{"id":"a12","tags":[1,2]},"meta":{"version":3}}"#).unwrap();
+/// assert_eq!(json.get_text(&"user/id"), Some("a12".to_string()));
+/// assert_eq!(json.pointer("/user/tags/1"), Some(simjson::JsonData::Num(2.0)));
+/// assert_eq!(json.raw(&"meta"), Some(r#"{"version":3}"#));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LazyJson<'a> {
+    json: &'a str,
+    tape: Vec<Node>,
+    options: ParseOptions,
+}
+
+impl<'a> LazyJson<'a> {
+    /// Indexes the first JSON value of the input, a syntax error is
+    /// detected at this time
+    pub fn new(json: &'a str) -> Result<Self, String> {
+        Self::with_options(json, &ParseOptions::default())
+    }
+
+    pub fn with_options(json: &'a str, options: &ParseOptions) -> Result<Self, String> {
+        Ok(LazyJson {
+            json,
+            tape: index(json, options)?,
+            options: options.clone(),
+        })
+    }
+
+    fn view(&self) -> View<'a, '_> {
+        View {
+            json: self.json,
+            tape: &self.tape,
+            options: &self.options,
+        }
+    }
+
+    /// Returns a value of the slash separated path, array elements are
+    /// addressed by index
+    pub fn get(&self, path: &impl AsRef<str>) -> Option<JsonData> {
+        let node = self.view().find(path.as_ref().split('/').map(Cow::Borrowed))?;
+        Some(crate::parse_with(self.view().slice(node), &self.options))
+    }
+
+    /// Returns a value of the path borrowing strings from the input
+    pub fn get_ref(&self, path: &impl AsRef<str>) -> Option<JsonRef<'a>> {
+        let node = self.view().find(path.as_ref().split('/').map(Cow::Borrowed))?;
+        Some(crate::parse_ref_with(self.view().slice(node), &self.options))
+    }
+
+    /// Returns a string of the path, same as [crate::get_path_as_text]
+    pub fn get_text(&self, path: &impl AsRef<str>) -> Option<String> {
+        match self.get(path)? {
+            JsonData::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the source text of a value of the path
+    pub fn raw(&self, path: &impl AsRef<str>) -> Option<&'a str> {
+        let node = self.view().find(path.as_ref().split('/').map(Cow::Borrowed))?;
+        Some(self.view().slice(node))
+    }
+
+    /// Returns a value addressed by JSON Pointer (RFC 6901)
+    pub fn pointer(&self, pointer: &str) -> Option<JsonData> {
+        let node = match pointer {
+            "" => self.tape.first().map(|_| 0)?,
+            _ => self.view().find(pointer_comps(pointer)?)?,
+        };
+        Some(crate::parse_with(self.view().slice(node), &self.options))
+    }
+}
+
+/// Splits JSON Pointer to unescaped reference tokens
+pub(crate) fn pointer_comps(pointer: &str) -> Option<impl Iterator<Item = Cow<'_, str>>> {
+    Some(pointer.strip_prefix('/')?.split('/').map(|comp| match comp.contains('~') {
+        true => Cow::Owned(comp.replace("~1", "/").replace("~0", "~")),
+        false => Cow::Borrowed(comp),
+    }))
+}
+
+/// Records positions of the first JSON value of the input on a tape
+pub(crate) fn index(json: &str, options: &ParseOptions) -> Result<Vec<Node>, String> {
+    let mut lexer = Lexer::with_options(options);
+    lexer.keep = false;
+    let mut tape = Vec::new();
+    let mut open = Vec::new();
+    let mut at = 0;
+    let done = |tape: &Vec<Node>, open: &Vec<_>| !tape.is_empty() && open.is_empty();
+    while at < json.len() && !done(&tape, &open) {
+        for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+            if done(&tape, &open) {
+                break;
+            }
+            let end = lexer.last.offset;
+            record(&mut tape, &mut open, &mut lexer, tok, json, end)?
+        }
+    }
+    if !done(&tape, &open)
+        && let Some(tok) = lexer.finish()
+    {
+        record(&mut tape, &mut open, &mut lexer, tok, json, json.len())?
+    }
+    Ok(tape)
+}
+
+/// Adds a node of the token, open containers come with starts of their
+/// keys kept for [DuplicateKeys::Error]
+fn record(
+    tape: &mut Vec<Node>,
+    open: &mut Vec<(usize, Option<HashMap<String, Position>>)>,
+    lexer: &mut Lexer,
+    tok: Tok,
+    json: &str,
+    num_end: usize,
+) -> Result<(), String> {
+    let start = lexer.position(&tok).offset;
+    let next = tape.len() + 1;
+    let (kind, end) = match tok {
+        Tok::StartObject | Tok::StartArray => {
+            open.push((tape.len(), None));
+            let kind = if tok == Tok::StartObject {
+                Kind::Obj
+            } else {
+                Kind::Arr
+            };
+            (kind, start)
+        }
+        Tok::EndObject | Tok::EndArray => {
+            let (container, _) = open.pop().expect("the lexer balances containers");
+            tape[container].end = start + 1;
+            tape[container].next = tape.len();
+            return Ok(());
+        }
+        // an unquoted JSON5 key is completed by a char following it
+        Tok::Key if !matches!(json.as_bytes()[start], b'"' | b'\'') => (Kind::Key, lexer.last.offset),
+        Tok::Key => (Kind::Key, lexer.last.offset + 1),
+        Tok::Num(_) => (Kind::Value, num_end),
+        Tok::Str | Tok::Bool(_) | Tok::Null => (Kind::Value, lexer.last.offset + 1),
+        Tok::Err(err) => return Err(err),
+    };
+    if kind == Kind::Key
+        && lexer.options.duplicate_keys == DuplicateKeys::Error
+        && let Some((_, starts)) = open.last_mut()
+    {
+        let starts = starts.get_or_insert_default();
+        let key = key_text(&json[start..end], &lexer.options);
+        if let Some(&first) = starts.get(key.as_ref()) {
+            return Err(lexer.duplicate(&key, first));
+        }
+        starts.insert(key.into_owned(), lexer.mark);
+    }
+    tape.push(Node {
+        kind,
+        start,
+        end,
+        next,
+    });
+    Ok(())
+}
+
+/// The input along with its tape, shared by [LazyJson] and [crate::JsonEdit]
+pub(crate) struct View<'a, 't> {
+    pub(crate) json: &'a str,
+    pub(crate) tape: &'t [Node],
+    pub(crate) options: &'t ParseOptions,
+}
+
+impl<'a> View<'a, '_> {
+    pub(crate) fn slice(&self, node: usize) -> &'a str {
+        let Node { start, end, .. } = self.tape[node];
+        &self.json[start..end]
+    }
+
+    pub(crate) fn find<'p>(&self, comps: impl Iterator<Item = Cow<'p, str>>) -> Option<usize> {
+        let mut cur = 0;
+        self.tape.first()?;
+        for comp in comps {
+            let node = self.tape[cur];
+            let mut inx = cur + 1;
+            match node.kind {
+                Kind::Obj => {
+                    let mut found = None;
+                    while inx < node.next {
+                        if self.is_key(inx, &comp) {
+                            found = Some(inx + 1);
+                            if self.options.duplicate_keys != DuplicateKeys::LastWins {
+                                break;
+                            }
+                        }
+                        inx = self.tape[inx + 1].next
+                    }
+                    cur = found?
+                }
+                Kind::Arr => {
+                    for _ in 0..comp.parse::<usize>().ok()? {
+                        if inx >= node.next {
+                            return None;
+                        }
+                        inx = self.tape[inx].next
+                    }
+                    if inx >= node.next {
+                        return None;
+                    }
+                    cur = inx
+                }
+                _ => return None,
+            }
+        }
+        Some(cur)
+    }
+
+    pub(crate) fn is_key(&self, node: usize, name: &str) -> bool {
+        key_text(self.slice(node), self.options) == name
+    }
+}
+
+/// Returns a key of its source text, quoted or not
+fn key_text<'k>(raw: &'k str, options: &ParseOptions) -> Cow<'k, str> {
+    if !raw.starts_with(['"', '\'']) {
+        return Cow::Borrowed(raw);
+    }
+    let key = &raw[1..raw.len() - 1];
+    if !key.contains('\\') {
+        return Cow::Borrowed(key);
+    }
+    match crate::parse_with(raw, options) {
+        JsonData::Text(key) => Cow::Owned(key),
+        _ => Cow::Borrowed(key),
+    }
+}
@@ -1,8 +1,39 @@
 //! A simple JSON parser with zero dependencies
-use std::{char, collections::HashMap};
+use std::{
+    borrow::Cow,
+    char,
+    collections::{HashMap, HashSet, hash_map::Entry},
+    hash::Hash,
+};
+
+mod arena;
+mod diagnostic;
+mod edit;
+mod encoding;
+mod extract;
+mod lazy;
+mod ndjson;
+mod recover;
+#[cfg(test)]
+mod tests;
+mod write;
+
+pub use arena::{JsonDoc, JsonNode};
+pub use diagnostic::{Diagnostic, try_parse, try_parse_with};
+pub use edit::JsonEdit;
+pub use encoding::{decode, parse_bytes, parse_bytes_with};
+pub use extract::{extract, extract_with};
+pub use lazy::LazyJson;
+pub use ndjson::{parse_lines, parse_lines_with};
+pub use recover::{parse_partial, parse_partial_with, parse_tolerant, parse_tolerant_with};
+pub use write::{NonFinite, WriteOptions, to_json, to_json_with};
 
 pub const VERSION: &str = env!("VERSION");
 
+/// Parsed JSON data
+///
+/// It's `Send` and `Sync`, so parsed data can be passed to other threads
+/// or shared between them.
 #[derive(Debug, Clone, PartialEq)]
 pub enum JsonData {
     Text(String),
@@ -15,7 +46,56 @@ pub enum JsonData {
     Err(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+const _: () = {
+    const fn send_sync<T: Send + Sync>() {}
+    send_sync::<JsonData>();
+    send_sync::<JsonRef>();
+    send_sync::<JsonDoc>();
+};
+
+/// JSON data borrowing strings and keys from the parsed text
+///
+/// A string without escapes is a slice of the input, so parsing doesn't
+/// allocate for it, other strings are owned. See [parse_ref].
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonRef<'a> {
+    Text(Cow<'a, str>),
+    Data(HashMap<Cow<'a, str>, JsonRef<'a>>),
+    Arr(Vec<JsonRef<'a>>),
+    Num(f64),
+    Bool(bool),
+    Null,
+    None,
+    Err(String),
+}
+
+impl JsonRef<'_> {
+    /// Converts to `JsonData` copying borrowed strings
+    pub fn into_owned(self) -> JsonData {
+        match self {
+            JsonRef::Text(text) => JsonData::Text(text.into_owned()),
+            JsonRef::Data(obj) => JsonData::Data(
+                obj.into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect(),
+            ),
+            JsonRef::Arr(arr) => JsonData::Arr(arr.into_iter().map(JsonRef::into_owned).collect()),
+            JsonRef::Num(num) => JsonData::Num(num),
+            JsonRef::Bool(b) => JsonData::Bool(b),
+            JsonRef::Null => JsonData::Null,
+            JsonRef::None => JsonData::None,
+            JsonRef::Err(err) => JsonData::Err(err),
+        }
+    }
+}
+
+impl From<JsonRef<'_>> for JsonData {
+    fn from(json: JsonRef<'_>) -> Self {
+        json.into_owned()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 enum JsonState {
     #[default]
     Start,
@@ -47,6 +127,11 @@ enum JsonState {
     NulN,
     NulU,
     NulL,
+    ObjIdent,
+    CommentStart,
+    LineComment,
+    BlockComment,
+    BlockCommentEnd,
 }
 
 #[macro_export]
@@ -82,10 +167,181 @@ pub fn get_path_as_text(json: &JsonData, path: &impl AsRef<str>) -> Option<Strin
 
 /// Parses a `&str` to `JsonData`
 pub fn parse(json: &str) -> JsonData {
-    // &impl AsRef<str>, Result<JsonData, String>
-    let binding = json.to_string();
-    let mut chars = binding.chars();
-    parse_fragment(&mut chars).0
+    parse_with(json, &ParseOptions::default())
+}
+
+/// Parses a `&str` to `JsonData` applying the specified options
+///
+/// The text is scanned byte wise, string runs without escapes are copied
+/// in bulk and UTF-8 is decoded only where a char is really needed.
+pub fn parse_with(json: &str, options: &ParseOptions) -> JsonData {
+    let mut lexer = Lexer::with_options(options);
+    let mut builder = Builder::default();
+    let mut at = 0;
+    while at < json.len() {
+        for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+            if let Some(value) = builder.token(tok, &mut lexer) {
+                return value;
+            }
+        }
+    }
+    match lexer.finish() {
+        Some(tok) => builder.token(tok, &mut lexer).unwrap_or(JsonData::None),
+        None => JsonData::None,
+    }
+}
+
+/// Parses a `&str` to [JsonRef] borrowing strings without escapes
+/// from the input
+/// ```
+/// let json = simjson::parse_ref(r#"{"name":"Malvika"}"#);
+/// let simjson::JsonRef::Data(obj) = &json else { panic!() };
+/// assert!(matches!(obj["name"], simjson::JsonRef::Text(std::borrow::Cow::Borrowed("Malvika"))));
+/// ```
+pub fn parse_ref(json: &str) -> JsonRef<'_> {
+    parse_ref_with(json, &ParseOptions::default())
+}
+
+/// Parses a `&str` to [JsonRef] applying the specified options
+pub fn parse_ref_with<'a>(json: &'a str, options: &ParseOptions) -> JsonRef<'a> {
+    let mut lexer = Lexer::with_options(options);
+    lexer.borrow = true;
+    let mut builder = RefBuilder::default();
+    let mut at = 0;
+    while at < json.len() {
+        for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+            if let Some(value) = builder.token(tok, &mut lexer, json) {
+                return value;
+            }
+        }
+    }
+    match lexer.finish() {
+        Some(tok) => builder.token(tok, &mut lexer, json).unwrap_or(JsonRef::None),
+        None => JsonRef::None,
+    }
+}
+
+/// Options controlling parsing
+///
+/// Nesting is bounded by `max_depth`, since although the parser keeps
+/// open objects and arrays in a heap stack, dropping or printing an
+/// extremely deep `JsonData` is still recursive. Other limits are off
+/// by default and can be set for parsing untrusted input, every limit
+/// produces own error.
+/// ```
+/// let options = simjson::ParseOptions { max_depth: 2, ..Default::default() };
+/// assert!(matches!(simjson::parse_with("[[[1]]]", &options), simjson::JsonData::Err(_)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// Maximum number of nested objects and arrays
+    pub max_depth: usize,
+    /// Maximum length of the whole input in bytes
+    pub max_input_len: usize,
+    /// Maximum length of a string value or a key in bytes after unescaping
+    pub max_string_len: usize,
+    /// Maximum number of members of one object
+    pub max_object_members: usize,
+    /// Maximum number of elements of one array
+    pub max_array_elements: usize,
+    /// Maximum length of a number literal
+    pub max_number_len: usize,
+    /// Accept JSON5: comments, trailing commas, unquoted keys, single
+    /// quoted strings, line continuations and more escapes in strings,
+    /// hexadecimal numbers, `Infinity` and `NaN`
+    pub json5: bool,
+    /// Accept JSONC: line and block comments and trailing commas in
+    /// arrays and objects, as in editor settings files
+    pub jsonc: bool,
+    /// What an object repeating a key gives
+    pub duplicate_keys: DuplicateKeys,
+    /// Accept `NaN`, `Infinity` and `-Infinity` as numbers, JSON5 accepts
+    /// them anyway
+    pub non_finite: bool,
+    /// What a `\\u` escape of a surrogate not forming a pair gives
+    pub lone_surrogates: LoneSurrogates,
+}
+
+/// Handling of a key repeated in one object
+///
+/// JSON doesn't define it and parsers differ, so a policy other than
+/// the default can be chosen for input checked by other parsers too.
+/// ```
+/// use simjson::{DuplicateKeys, JsonData, ParseOptions};
+/// let options = |duplicate_keys| ParseOptions { duplicate_keys, ..Default::default() };
+/// let json = r#"{"a":1,"a":2}"#;
+/// assert_eq!(simjson::parse(json), simjson::parse("{\"a\":2}"));
+/// assert_eq!(simjson::parse_with(json, &options(DuplicateKeys::FirstWins)), simjson::parse("{\"a\":1}"));
+/// assert_eq!(simjson::parse_with(json, &options(DuplicateKeys::Collect)), simjson::parse("{\"a\":[1,2]}"));
+/// assert_eq!(
+///     simjson::parse_with(json, &options(DuplicateKeys::Error)),
+///     JsonData::Err("an error: duplicate key \"a\" at 8:1, first at 2:1".to_string())
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DuplicateKeys {
+    /// A later value replaces the earlier one
+    #[default]
+    LastWins,
+    /// A later value is dropped
+    FirstWins,
+    /// The input is an error reporting positions of both keys
+    Error,
+    /// Values of a repeated key are gathered to an array in their order,
+    /// paths of [LazyJson], [JsonEdit] and [JsonDoc] lead to the first one
+    Collect,
+}
+
+/// Handling of a `\\u` escape of a surrogate not forming a pair
+///
+/// Such an escape is valid JSON but encodes no char, JavaScript produces
+/// it by splitting a string inside a pair.
+/// ```
+/// use simjson::{JsonData, LoneSurrogates, ParseOptions};
+/// let options = |lone_surrogates| ParseOptions { lone_surrogates, ..Default::default() };
+/// let json = r#""a\ud83db""#;
+/// assert_eq!(simjson::parse(json), JsonData::Text("a\u{fffd}b".to_string()));
+/// assert_eq!(
+///     simjson::parse_with(json, &options(LoneSurrogates::Escape)),
+///     JsonData::Text("a\\ud83db".to_string())
+/// );
+/// assert_eq!(
+///     simjson::parse_with(json, &options(LoneSurrogates::Error)),
+///     JsonData::Err("an error: lone surrogate \\ud83d at 3:1".to_string())
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LoneSurrogates {
+    /// The surrogate is replaced by U+FFFD
+    #[default]
+    Replace,
+    /// The input is an error at the escape
+    Error,
+    /// The escape is written into the text as six chars `\\ud83d`, in
+    /// lowercase as `JSON.stringify` writes it
+    ///
+    /// It's escaped text, not the surrogate: the string can't be told from
+    /// an escaped backslash followed by `ud83d`, and [crate::to_json] writes
+    /// it back as `\\\\ud83d`.
+    Escape,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_depth: 128,
+            max_input_len: usize::MAX,
+            max_string_len: usize::MAX,
+            max_object_members: usize::MAX,
+            max_array_elements: usize::MAX,
+            max_number_len: usize::MAX,
+            json5: false,
+            jsonc: false,
+            duplicate_keys: DuplicateKeys::LastWins,
+            non_finite: false,
+            lone_surrogates: LoneSurrogates::Replace,
+        }
+    }
 }
 
 pub struct JsonStr<'a> {
@@ -108,962 +364,1482 @@ impl Iterator for JsonStr<'_> {
 ///
 /// It stops parsing as reaches the end of JSON data, however the input
 /// `Iterator` can produce more data,so sequention call of the function
-/// will parse a next fragment until the iterator is completely exausted [JsonData::None]. 
+/// will parse a next fragment until the iterator is completely exausted [JsonData::None].
+/// A top level number is completed by a following char which is consumed
+/// and returned along with the data.
 pub fn parse_fragment<I>(chars: &mut I) -> (JsonData, char)
 where
     I: Iterator<Item = char> + ?Sized,
 {
-    let mut field_value = String::with_capacity(96);
-    let mut field_name = String::with_capacity(96);
-    let mut num_value = 0.0;
-    let mut mant_dig = 1.0;
-    let mut exp_val = 0.0;
-    let mut neg = false;
-    let mut neg_exp = false;
-    let mut arr = Vec::new();
-    let mut obj = HashMap::new();
-    let mut dig_inx = String::with_capacity(4);
-    let mut surrogates = [0_u16, 0];
-    let mut surrogate_first = true;
-    let mut state = Default::default();
-    let mut char_pos = 0;
-    let mut char_line = 1;
-    while let Some(c) = chars.next() {
-        if c == '\n' {
-            char_pos = 0;
-            char_line += 1;
+    parse_fragment_with(chars, &ParseOptions::default())
+}
+
+/// Parses JSON data using `char` `Iterator` applying the specified options
+///
+/// Objects and arrays are built using an explicit stack, so a deep nesting
+/// can't exhaust the thread stack.
+pub fn parse_fragment_with<I>(chars: &mut I, options: &ParseOptions) -> (JsonData, char)
+where
+    I: Iterator<Item = char> + ?Sized,
+{
+    let mut lexer = Lexer::with_options(options);
+    let mut builder = Builder::default();
+    for c in chars {
+        for tok in lexer.push(c).into_iter().flatten() {
+            if let Some(value) = builder.token(tok, &mut lexer) {
+                return (value, c);
+            }
         }
-        match c {
-            '"' => match state {
-                JsonState::Start => {
-                    field_value.clear();
-                    state = JsonState::ObjData
+    }
+    if let Some(tok) = lexer.finish()
+        && let Some(value) = builder.token(tok, &mut lexer)
+    {
+        return (value, char::from_u32(0).unwrap());
+    }
+    (JsonData::None, char::from_u32(0).unwrap())
+}
+
+/// A location in JSON text, `line` and `column` count from 1 and
+/// `offset` is in bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+/// A container opened and not yet closed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Nest {
+    Obj,
+    Arr,
+}
+
+/// A token of [Lexer], text of `Key` and `Str` is kept in [Lexer::text]
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    Key,
+    Str,
+    Num(f64),
+    Bool(bool),
+    Null,
+    Err(String),
+}
+
+/// Resumable JSON state machine consuming one `char` at time
+///
+/// The nesting is kept in an explicit stack, so the machine can be
+/// suspended at any character and continued when more input arrives.
+#[derive(Debug, Clone)]
+struct Lexer {
+    state: JsonState,
+    nest: Vec<(Nest, usize)>,
+    text: String,
+    dig_inx: String,
+    /// A high surrogate waiting for a low one and the position of its
+    /// escape
+    surrogate: Option<(u32, Position)>,
+    quote: char,
+    cr: bool,
+    resume: JsonState,
+    comma: bool,
+    keep: bool,
+    dropped: usize,
+    borrow: bool,
+    raw: Option<usize>,
+    span: Option<(usize, usize)>,
+    here: Position,
+    last: Position,
+    mark: Position,
+    num_mark: Position,
+    /// An error is caused by a limit, so parsing can't be recovered
+    limited: bool,
+    fault: Option<Fault>,
+    options: ParseOptions,
+}
+
+/// The last error of [Lexer], what is wrong without the position and
+/// the state it happened in
+#[derive(Debug, Clone)]
+struct Fault {
+    what: String,
+    state: JsonState,
+    at: Position,
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Lexer {
+            state: JsonState::Start,
+            nest: Vec::new(),
+            text: String::with_capacity(96),
+            dig_inx: String::with_capacity(4),
+            surrogate: None,
+            quote: '"',
+            cr: false,
+            resume: JsonState::Start,
+            comma: false,
+            keep: true,
+            dropped: 0,
+            borrow: false,
+            raw: None,
+            span: None,
+            here: Position::default(),
+            last: Position::default(),
+            mark: Position::default(),
+            num_mark: Position::default(),
+            limited: false,
+            fault: None,
+            options: ParseOptions::default(),
+        }
+    }
+}
+
+impl Lexer {
+    fn with_options(options: &ParseOptions) -> Self {
+        Lexer {
+            options: options.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Consumes a next char and returns up to two completed tokens
+    fn push(&mut self, c: char) -> [Option<Tok>; 2] {
+        self.last = self.here;
+        let res = match self.state {
+            JsonState::ObjData | JsonState::ObjName => [self.string(c), None],
+            JsonState::ObjIdent => self.ident(c),
+            JsonState::CommentStart
+            | JsonState::LineComment
+            | JsonState::BlockComment
+            | JsonState::BlockCommentEnd => [self.comment(c), None],
+            JsonState::EscValue | JsonState::EscName => [self.escape(c), None],
+            JsonState::UniDigVal | JsonState::UniDigName => [self.unicode(c), None],
+            JsonState::NegNum
+            | JsonState::NumValue
+            | JsonState::MantissaValue
+            | JsonState::ExpExpValue
+            | JsonState::NegExpNum
+            | JsonState::ExpNumValue => match c {
+                _ if self.options.json5
+                    && (c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-'))
+                    || self.options.non_finite && c.is_ascii_alphabetic() && !matches!(c, 'e' | 'E') =>
+                {
+                    self.text.push(c);
+                    self.state = JsonState::NumValue;
+                    [None, None]
                 }
-                JsonState::ObjState => {
-                    state = JsonState::ObjName;
-                    field_name.clear();
+                '0'..='9' => {
+                    self.text.push(c);
+                    self.state = match self.state {
+                        JsonState::NegNum => JsonState::NumValue,
+                        JsonState::ExpExpValue | JsonState::NegExpNum => JsonState::ExpNumValue,
+                        state => state,
+                    };
+                    [None, None]
                 }
-                JsonState::ObjData => return (JsonData::Text(field_value.clone()), c),
-                JsonState::ObjName => {
-                    state = JsonState::ExpNameSep;
+                '.' if self.state == JsonState::NumValue => {
+                    self.text.push(c);
+                    self.state = JsonState::MantissaValue;
+                    [None, None]
                 }
-                JsonState::EscName => {
-                    field_name.push(c);
-                    state = JsonState::ObjName
+                'e' | 'E'
+                    if matches!(self.state, JsonState::NumValue | JsonState::MantissaValue) =>
+                {
+                    self.text.push(c);
+                    self.state = JsonState::ExpExpValue;
+                    [None, None]
                 }
-                JsonState::EscValue => {
-                    field_value.push(c);
-                    state = JsonState::ObjData
+                '-' | '+' if self.state == JsonState::ExpExpValue => {
+                    self.text.push(c);
+                    self.state = JsonState::NegExpNum;
+                    [None, None]
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+                _ => match self.number(c) {
+                    Tok::Err(err) => [Some(Tok::Err(err)), None],
+                    num => [Some(num), self.structural(c)],
+                },
             },
-            ' ' | '\t' | '\r' | '\n' => {
-                match state {
-                    JsonState::Start | JsonState::ArrState | JsonState::ArrNext => (),
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::ObjState | JsonState::ObjExpEnd => (),
-                    JsonState::NumValue => {
-                        if neg {
-                            num_value = -num_value
+            JsonState::BoolT
+            | JsonState::BoolR
+            | JsonState::BoolU
+            | JsonState::BoolF
+            | JsonState::BoolA
+            | JsonState::BoolL
+            | JsonState::BoolS
+            | JsonState::NulN
+            | JsonState::NulU
+            | JsonState::NulL => [self.literal(c), None],
+            JsonState::ErrState => [None, None],
+            _ => [self.structural(c), None],
+        };
+        let res = match self.limit(c) {
+            Some(err) => [Some(err), None],
+            None => res,
+        };
+        self.here.offset += c.len_utf8();
+        if c == '\n' {
+            self.here.line += 1;
+            self.here.column = 1
+        } else {
+            self.here.column += 1
+        }
+        res
+    }
+
+    /// Consumes the text starting at the byte `at` until some tokens are
+    /// completed or the text is over
+    ///
+    /// It is a fast path of [Lexer::push], a run of string chars up to
+    /// a quote or an escape is appended at once without decoding.
+    fn scan(&mut self, text: &str, at: &mut usize) -> [Option<Tok>; 2] {
+        let bytes = text.as_bytes();
+        while *at < bytes.len() {
+            if matches!(self.state, JsonState::ObjData | JsonState::ObjName)
+                && self.surrogate.is_none()
+                && self.quote == '"'
+                && !self.cr
+            {
+                let run = find_quote(&bytes[*at..]);
+                if run > 0
+                    && self.text.len() + self.dropped + self.raw_len() + run
+                        <= self.options.max_string_len
+                    && self.here.offset + run <= self.options.max_input_len
+                {
+                    let run = &text[*at..*at + run];
+                    if self.raw.is_none() {
+                        if self.keep {
+                            self.text.push_str(run)
+                        } else {
+                            self.dropped += run.len()
                         }
-                        return (JsonData::Num(num_value), c);
                     }
-                    JsonState::ExpNameSep => {}
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        //field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+                    self.advance(run);
+                    *at += run.len();
+                    continue;
                 }
             }
-            '[' => match state {
-                JsonState::Start => {
-                    arr.clear();
-                    let fragment = parse_fragment(chars);
-                    arr.push(fragment.0);
-                    match fragment.1 {
-                        ',' => {
-                            loop {
-                                let fragment = parse_fragment(chars);
-                                arr.push(fragment.0);
-                                match fragment.1 {
-                                    ']' => return (JsonData::Arr(arr.clone()), c),
-                                    ',' => continue,
-                                    _ => break,
-                                }
-                            }
-                            state = JsonState::ArrNext
-                        }
-                        ']' => return (JsonData::Arr(arr.clone()), c),
-                        _ => state = JsonState::ArrState,
-                    }
-                }
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::ArrState => arr.push(parse_fragment(chars).0),
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
-                }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            '{' => match state {
-                JsonState::Start => {
-                    state = JsonState::ObjState;
-                    obj.clear();
-                }
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
-                }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            '\\' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => state = JsonState::EscName,
-                JsonState::ObjData => state = JsonState::EscValue,
-                JsonState::EscName => {
-                    field_name.push(c);
-                    state = JsonState::ObjName
-                }
-                JsonState::EscValue => {
-                    field_value.push(c);
-                    state = JsonState::ObjData
-                }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            '/' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::EscName => {
-                    field_name.push(c);
-                    state = JsonState::ObjName
-                }
-                JsonState::EscValue => {
-                    field_value.push(c);
-                    state = JsonState::ObjData
-                }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            ':' => {
-                match state {
-                    JsonState::Start => state = JsonState::ErrState,
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::ExpNameSep => {
-                        let fragment = parse_fragment(chars);
-                        obj.insert(field_name.clone(), fragment.0);
-                        // chars.next_back();
-                        match fragment.1 {
-                            '}' => return (JsonData::Data(obj.clone()), c),
-                            ',' => state = JsonState::ObjState,
-                            _ => state = JsonState::ObjExpEnd,
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-                }
+            let c = match bytes[*at] {
+                b @ 0..0x80 => b as char,
+                _ => text[*at..].chars().next().unwrap(),
+            };
+            if c == '\\'
+                && matches!(self.state, JsonState::ObjData | JsonState::ObjName)
+                && let Some(start) = self.raw.take()
+            {
+                self.text.push_str(&text[start..*at])
             }
-            ']' => {
-                match state {
-                    JsonState::Start => state = JsonState::ArrState,
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::MantissaValue | JsonState::NumValue => {
-                        if neg {
-                            num_value = -num_value
-                        }
-                        return (JsonData::Num(num_value), c);
-                        // arr.push(JsonData::Num(num_value));
-                        // return (JsonData::Arr(arr.clone()),c)
-                    }
-                    JsonState::ExpNumValue => {
-                        if neg {
-                            num_value = -num_value
-                        }
-                        if neg_exp {
-                            exp_val = -exp_val
-                        }
-                        return (JsonData::Num(num_value * 10.0_f64.powf(exp_val)), c);
-                    }
-                    JsonState::ArrState | JsonState::ArrNext => {
-                        return (JsonData::Arr(arr.clone()), c)
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-                }
+            *at += c.len_utf8();
+            let res = self.push(c);
+            if res[0].is_some() {
+                return res;
             }
-            '}' => match state {
-                JsonState::Start => return (JsonData::None, c),
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::NumValue | JsonState::MantissaValue => {
-                    if neg {
-                        num_value = -num_value
-                    }
-                    return (JsonData::Num(num_value), c);
-                }
-                JsonState::ExpNumValue => {
-                    if neg {
-                        num_value = -num_value
-                    }
-                    if neg_exp {
-                        exp_val = -exp_val
-                    }
-                    return (JsonData::Num(num_value * 10.0_f64.powf(exp_val)), c);
-                }
-                JsonState::ObjExpEnd => {
-                    return (JsonData::Data(obj.clone()), char::from_u32(0).unwrap())
+        }
+        [None, None]
+    }
+
+    /// Moves the current position past a consumed string run
+    fn advance(&mut self, run: &str) {
+        self.here.offset += run.len();
+        let tail = match run.rfind('\n') {
+            Some(nl) => {
+                self.here.line += run.bytes().filter(|&b| b == b'\n').count();
+                self.here.column = 1;
+                &run[nl + 1..]
+            }
+            None => run,
+        };
+        self.here.column += tail.bytes().filter(|&b| b & 0xC0 != 0x80).count()
+    }
+
+    /// Checks the resource limits after the char was consumed
+    fn limit(&mut self, c: char) -> Option<Tok> {
+        let options = &self.options;
+        let what = if self.state == JsonState::ErrState {
+            return None;
+        } else if self.here.offset + c.len_utf8() > options.max_input_len {
+            format!("input length exceeds {} bytes", options.max_input_len)
+        } else {
+            match self.state {
+                JsonState::ObjData
+                | JsonState::ObjName
+                | JsonState::ObjIdent
+                | JsonState::EscValue
+                | JsonState::EscName
+                | JsonState::UniDigVal
+                | JsonState::UniDigName
+                    if self.text.len()
+                        + self.dropped
+                        + self.raw.map_or(0, |start| {
+                            (self.here.offset + c.len_utf8()).saturating_sub(start)
+                        })
+                        > options.max_string_len =>
+                {
+                    format!("string length exceeds {} bytes", options.max_string_len)
                 }
-                JsonState::ObjState => return (JsonData::None, c),
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
+                JsonState::NegNum
+                | JsonState::NumValue
+                | JsonState::MantissaValue
+                | JsonState::ExpExpValue
+                | JsonState::NegExpNum
+                | JsonState::ExpNumValue
+                    if self.text.len() > options.max_number_len =>
+                {
+                    format!("number length exceeds {} chars", options.max_number_len)
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+                _ => return None,
+            }
+        };
+        self.exceeded(what)
+    }
+
+    /// Counts a member or an element started in the current container
+    fn count(&mut self, c: char) -> Option<Tok> {
+        let starts = match self.state {
+            JsonState::ArrState => c != ']',
+            JsonState::ObjState => c == '"' || self.options.json5 && c != '}',
+            _ => false,
+        };
+        if !starts {
+            return None;
+        }
+        let (nest, count) = self.nest.last_mut()?;
+        *count += 1;
+        let (limit, what) = match nest {
+            Nest::Obj => (self.options.max_object_members, "object members"),
+            Nest::Arr => (self.options.max_array_elements, "array elements"),
+        };
+        if *count > limit {
+            return self.exceeded(format!("{what} exceed {limit}"));
+        }
+        None
+    }
+
+    fn exceeded(&mut self, what: String) -> Option<Tok> {
+        self.limited = true;
+        self.malformed(what, self.here)
+    }
+
+    /// Reports what is wrong with a construct starting at the position
+    fn malformed(&mut self, what: String, at: Position) -> Option<Tok> {
+        let err = format!("an error: {what} at {}:{}", at.column, at.line);
+        self.fail(what, at);
+        Some(Tok::Err(err))
+    }
+
+    /// Reports a key repeating the one at the position
+    fn duplicate(&mut self, key: &str, first: Position) -> String {
+        let at = self.mark;
+        self.fail(
+            format!("duplicate key {key:?}, first at {}:{}", first.column, first.line),
+            at,
+        );
+        format!(
+            "an error: duplicate key {key:?} at {}:{}, first at {}:{}",
+            at.column, at.line, first.column, first.line
+        )
+    }
+
+    fn fail(&mut self, what: String, at: Position) {
+        self.fault = Some(Fault {
+            what,
+            state: self.state,
+            at,
+        });
+        self.state = JsonState::ErrState
+    }
+
+    /// Signals the end of input, completes a pending number
+    fn finish(&mut self) -> Option<Tok> {
+        if self.state == JsonState::LineComment {
+            self.state = self.resume
+        }
+        match self.state {
+            JsonState::ErrState => None,
+            JsonState::CommentStart | JsonState::BlockComment | JsonState::BlockCommentEnd => {
+                self.malformed("unterminated comment".to_string(), self.here)
+            }
+            JsonState::Start if self.nest.is_empty() => None,
+            JsonState::NegNum
+            | JsonState::NumValue
+            | JsonState::MantissaValue
+            | JsonState::ExpExpValue
+            | JsonState::NegExpNum
+            | JsonState::ExpNumValue
+                if self.nest.is_empty() =>
+            {
+                Some(self.number(' '))
+            }
+            JsonState::ObjData
+            | JsonState::ObjName
+            | JsonState::EscValue
+            | JsonState::EscName
+            | JsonState::UniDigVal
+            | JsonState::UniDigName => self.malformed("unterminated string".to_string(), self.mark),
+            _ => self.malformed(format!("{}, found the end of input", self.expected()), self.here),
+        }
+    }
+
+    /// Returns where a token starts, closing brackets are reported at the last char
+    /// and an error where its message points
+    ///
+    /// A number is completed by a next char which may start another token,
+    /// so the number start is kept apart.
+    fn position(&self, tok: &Tok) -> Position {
+        match tok {
+            Tok::Err(_) => self.fault.as_ref().map_or(self.last, |fault| fault.at),
+            Tok::EndObject | Tok::EndArray => self.last,
+            Tok::Num(_) => self.num_mark,
+            _ => self.mark,
+        }
+    }
+
+    /// Tells if the machine is between top level values
+    fn is_idle(&self) -> bool {
+        self.state == JsonState::Start && self.nest.is_empty()
+    }
+
+    /// Tells if the machine is in whitespace or a comment between values
+    fn is_blank(&self) -> bool {
+        matches!(
+            self.state,
+            JsonState::Start
+                | JsonState::CommentStart
+                | JsonState::LineComment
+                | JsonState::BlockComment
+                | JsonState::BlockCommentEnd
+        )
+    }
+
+    /// Reports a char which doesn't fit the state
+    fn error(&mut self, c: char) -> Option<Tok> {
+        match self.state {
+            JsonState::NegNum
+            | JsonState::NumValue
+            | JsonState::MantissaValue
+            | JsonState::ExpExpValue
+            | JsonState::NegExpNum
+            | JsonState::ExpNumValue => {
+                let what = format!("invalid number {}", self.text);
+                self.malformed(what, self.mark)
+            }
+            _ => self.malformed(format!("{}, found {c:?}", self.expected()), self.here),
+        }
+    }
+
+    /// Tells what the state is waiting for
+    fn expected(&self) -> &'static str {
+        match self.state {
+            JsonState::ArrState if self.comma => "expected a value after ','",
+            JsonState::ArrState => "expected a value or ']'",
+            JsonState::ObjState if self.comma => "expected a key after ','",
+            JsonState::ObjState => "expected a key or '}'",
+            JsonState::ExpNameSep | JsonState::ObjIdent => "expected ':' after key",
+            JsonState::BoolT | JsonState::BoolR | JsonState::BoolU => "expected true",
+            JsonState::BoolF | JsonState::BoolA | JsonState::BoolL | JsonState::BoolS => "expected false",
+            JsonState::NulN | JsonState::NulU | JsonState::NulL => "expected null",
+            JsonState::UniDigVal | JsonState::UniDigName => "expected a hex digit of \\u escape",
+            // a number is complete at the end of input
+            JsonState::ArrNext
+            | JsonState::ObjExpEnd
+            | JsonState::NegNum
+            | JsonState::NumValue
+            | JsonState::MantissaValue
+            | JsonState::ExpExpValue
+            | JsonState::NegExpNum
+            | JsonState::ExpNumValue => match self.nest.last() {
+                Some((Nest::Arr, _)) => "expected ',' or ']' after array element",
+                Some((Nest::Obj, _)) => "expected ',' or '}' after object member",
+                None => "expected a value",
             },
-            '0'..='9' => {
-                match state {
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::NumValue => {
-                        num_value = num_value * 10.0 + c.to_digit(10).unwrap() as f64
-                    }
-                    JsonState::MantissaValue => {
-                        mant_dig *= 10.0;
-                        num_value += (c.to_digit(10).unwrap() as f64) / mant_dig;
-                    }
-                    JsonState::ExpNumValue => {
-                        // state = JsonState::NumValue;
-                        exp_val = exp_val * 10.0 + c.to_digit(10).unwrap() as f64
-                    }
-                    JsonState::NegExpNum => {
-                        state = JsonState::ExpNumValue;
-                        exp_val = c.to_digit(10).unwrap() as _;
-                        neg_exp = true;
-                    }
-                    JsonState::ExpExpValue => {
-                        state = JsonState::ExpNumValue;
-                        neg_exp = false;
-                        exp_val = c.to_digit(10).unwrap() as _
-                    }
-                    JsonState::Start => {
-                        state = JsonState::NumValue;
-                        mant_dig = 1.0;
-                        exp_val = 0.0;
-                        neg = false;
-                        num_value = c.to_digit(10).unwrap() as _
-                    }
-                    JsonState::NegNum => {
-                        state = JsonState::NumValue;
-                        mant_dig = 1.0;
-                        exp_val = 0.0;
-                        num_value = c.to_digit(10).unwrap() as _;
-                        neg = true;
-                    }
-                    JsonState::ArrState => {
-                        state = JsonState::NumValue;
-                        mant_dig = 1.0;
-                        exp_val = 0.0;
-                        neg = false;
-                        num_value = c.to_digit(10).unwrap() as _
-                    }
-                    JsonState::UniDigVal | JsonState::UniDigName => {
-                        dig_inx.push(c);
-                        if dig_inx.len() == 4 {
-                            match u32::from_str_radix(&dig_inx, 16) {
-                                Ok(number) => {
-                                    //println!("The number is: {}", number);
-                                    match std::char::from_u32(number) {
-                                        // as u32
-                                        Some(character) => {
-                                            //println!("The character is: {}", character);
-                                            match state {
-                                                JsonState::UniDigVal => {
-                                                    field_value.push(character);
-                                                    state = JsonState::ObjData
-                                                }
-                                                JsonState::UniDigName => {
-                                                    field_name.push(character);
-                                                    state = JsonState::ObjName
-                                                }
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                        None => {
-                                            //eprintln!("Error: Invalid Unicode scalar value!");
-                                            if !surrogate_first {
-                                                surrogates[1] = number as u16;
-                                                match char::decode_utf16(surrogates).next() {
-                                                    Some(Ok(character)) => match state {
-                                                        JsonState::UniDigVal => {
-                                                            field_value.push(character)
-                                                        }
-                                                        JsonState::UniDigName => {
-                                                            field_name.push(character)
-                                                        }
-                                                        _ => unreachable!(),
-                                                    },
-                                                    Some(Err(_)) => match state {
-                                                        JsonState::UniDigVal => field_value
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        JsonState::UniDigName => field_name
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        _ => unreachable!(),
-                                                    },
-                                                    None => unreachable!(),
-                                                }
-                                            } else {
-                                                surrogates[0] = number as u16;
-                                            }
-                                            surrogate_first = !surrogate_first;
-                                            match state {
-                                                JsonState::UniDigVal => state = JsonState::ObjData,
-                                                JsonState::UniDigName => state = JsonState::ObjName,
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to convert: {}", e);
-                                    match state {
-                                        JsonState::UniDigVal => state = JsonState::ObjData,
-                                        JsonState::UniDigName => state = JsonState::ObjName,
-                                        _ => unreachable!(),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-                }
+            _ => "expected a value",
+        }
+    }
+
+    fn open(&mut self, nest: Nest) -> Option<Tok> {
+        if self.nest.len() >= self.options.max_depth {
+            return self.exceeded(format!("nesting depth exceeds {}", self.options.max_depth));
+        }
+        self.nest.push((nest, 0));
+        self.comma = false;
+        match nest {
+            Nest::Obj => {
+                self.state = JsonState::ObjState;
+                Some(Tok::StartObject)
             }
-            '.' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::NumValue => {
-                    field_value.push(c);
-                    state = JsonState::MantissaValue
-                }
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
+            Nest::Arr => {
+                self.state = JsonState::ArrState;
+                Some(Tok::StartArray)
+            }
+        }
+    }
+
+    /// Comments and trailing commas are accepted by both JSON5 and JSONC
+    fn relaxed(&self) -> bool {
+        self.options.json5 || self.options.jsonc
+    }
+
+    fn after_value(&mut self) {
+        self.state = match self.nest.last() {
+            Some((Nest::Obj, _)) => JsonState::ObjExpEnd,
+            Some((Nest::Arr, _)) => JsonState::ArrNext,
+            None => JsonState::Start,
+        }
+    }
+
+    fn structural(&mut self, c: char) -> Option<Tok> {
+        // a byte order mark may start the text
+        if matches!(c, ' ' | '\t' | '\r' | '\n')
+            || c == '\u{feff}' && (self.here.offset == 0 || self.options.json5)
+            || self.options.json5 && c.is_whitespace()
+        {
+            return None;
+        }
+        if c == '/' && self.relaxed() {
+            self.resume = self.state;
+            self.state = JsonState::CommentStart;
+            return None;
+        }
+        if !matches!(c, ']' | '}' | ',' | ':') {
+            self.mark = self.here
+        }
+        if let Some(err) = self.count(c) {
+            return Some(err);
+        }
+        match (&self.state, c) {
+            (JsonState::Start | JsonState::ArrState, '{') => self.open(Nest::Obj),
+            (JsonState::Start | JsonState::ArrState, '[') => self.open(Nest::Arr),
+            (JsonState::Start | JsonState::ArrState, '"' | '\'')
+                if c == '"' || self.options.json5 =>
+            {
+                self.begin_string(c);
+                self.state = JsonState::ObjData;
+                None
+            }
+            (JsonState::Start | JsonState::ArrState, '-' | '+' | '.' | '0'..='9' | 'I' | 'N')
+                if matches!(c, '-' | '0'..='9')
+                    || self.options.json5
+                    || self.options.non_finite && matches!(c, 'I' | 'N') =>
+            {
+                self.text.clear();
+                self.text.push(c);
+                self.state = if c == '-' {
+                    JsonState::NegNum
+                } else {
+                    JsonState::NumValue
+                };
+                None
+            }
+            (JsonState::Start | JsonState::ArrState, 't') => {
+                self.state = JsonState::BoolT;
+                None
+            }
+            (JsonState::Start | JsonState::ArrState, 'f') => {
+                self.state = JsonState::BoolF;
+                None
+            }
+            (JsonState::Start | JsonState::ArrState, 'n') => {
+                self.state = JsonState::NulN;
+                None
+            }
+            (JsonState::ArrState, ']') | (JsonState::ArrNext, ']')
+                if !self.comma || self.relaxed() =>
+            {
+                self.nest.pop();
+                self.after_value();
+                Some(Tok::EndArray)
+            }
+            (JsonState::ArrNext, ',') => {
+                self.state = JsonState::ArrState;
+                self.comma = true;
+                None
+            }
+            (JsonState::ObjState, '"' | '\'') if c == '"' || self.options.json5 => {
+                self.begin_string(c);
+                self.state = JsonState::ObjName;
+                None
+            }
+            (JsonState::ObjState, _)
+                if self.options.json5 && (c.is_alphabetic() || c == '$' || c == '_') =>
+            {
+                self.text.clear();
+                self.dropped = 0;
+                if self.borrow {
+                    self.raw = Some(self.here.offset)
+                } else if self.keep {
+                    self.text.push(c)
+                } else {
+                    self.dropped = c.len_utf8()
                 }
-                _ => error!(),
-            },
-            '-' => match state {
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::Start => state = JsonState::NegNum,
-                JsonState::ExpExpValue => state = JsonState::NegExpNum,
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
+                self.state = JsonState::ObjIdent;
+                None
+            }
+            (JsonState::ObjState | JsonState::ObjExpEnd, '}')
+                if !self.comma || self.relaxed() =>
+            {
+                self.nest.pop();
+                self.after_value();
+                Some(Tok::EndObject)
+            }
+            (JsonState::ExpNameSep, ':') => {
+                self.state = JsonState::Start;
+                None
+            }
+            (JsonState::ObjExpEnd, ',') => {
+                self.state = JsonState::ObjState;
+                self.comma = true;
+                None
+            }
+            _ => self.error(c),
+        }
+    }
+
+    fn begin_string(&mut self, quote: char) {
+        self.quote = quote;
+        self.text.clear();
+        self.dropped = 0;
+        if self.borrow {
+            self.raw = Some(self.here.offset + 1)
+        }
+    }
+
+    /// Returns a length of the string still not copied from the input
+    fn raw_len(&self) -> usize {
+        self.raw
+            .map_or(0, |start| self.here.offset.saturating_sub(start))
+    }
+
+    /// Returns a just completed string, it's borrowed from the input
+    /// when the string has no escapes and the lexer is in the borrow mode
+    fn take_text<'a>(&mut self, json: &'a str) -> Cow<'a, str> {
+        match self.span.take() {
+            Some((start, end)) => Cow::Borrowed(&json[start..end]),
+            None => Cow::Owned(std::mem::take(&mut self.text)),
+        }
+    }
+
+    fn string(&mut self, c: char) -> Option<Tok> {
+        if self.cr {
+            self.cr = false;
+            if c == '\n' {
+                return None;
+            }
+        }
+        if c != '\\'
+            && let Some((high, at)) = self.surrogate.take()
+            && let Some(err) = self.lone(high, at)
+        {
+            return Some(err);
+        }
+        match c {
+            _ if c == self.quote => {
+                if let Some(start) = self.raw.take() {
+                    self.span = Some((start, self.here.offset))
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            'E' => {
-                match state {
-                    JsonState::Start => state = JsonState::ErrState,
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::MantissaValue | JsonState::NumValue => {
-                        //exp_val = 0.0
-                        state = JsonState::ExpExpValue
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    JsonState::UniDigVal | JsonState::UniDigName => {
-                        dig_inx.push(c);
-                        if dig_inx.len() == 4 {
-                            match u32::from_str_radix(&dig_inx, 16) {
-                                Ok(number) => {
-                                    //println!("The number is: {}", number);
-                                    match std::char::from_u32(number) {
-                                        Some(character) => {
-                                            //println!("The character is: {}", character);
-                                            match state {
-                                                JsonState::UniDigVal => {
-                                                    field_value.push(character);
-                                                    state = JsonState::ObjData
-                                                }
-                                                JsonState::UniDigName => {
-                                                    field_name.push(character);
-                                                    state = JsonState::ObjName
-                                                }
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                        None => {
-                                            //eprintln!("Error: Invalid Unicode scalar value!");
-                                            if !surrogate_first {
-                                                surrogates[1] = number as u16;
-                                                match char::decode_utf16(surrogates).next() {
-                                                    Some(Ok(character)) => match state {
-                                                        JsonState::UniDigVal => {
-                                                            field_value.push(character)
-                                                        }
-                                                        JsonState::UniDigName => {
-                                                            field_name.push(character)
-                                                        }
-                                                        _ => unreachable!(),
-                                                    },
-                                                    Some(Err(_)) => match state {
-                                                        JsonState::UniDigVal => field_value
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        JsonState::UniDigName => field_name
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        _ => unreachable!(),
-                                                    },
-                                                    None => unreachable!(),
-                                                }
-                                            } else {
-                                                surrogates[0] = number as u16;
-                                            }
-                                            surrogate_first = !surrogate_first;
-                                            match state {
-                                                JsonState::UniDigVal => state = JsonState::ObjData,
-                                                JsonState::UniDigName => state = JsonState::ObjName,
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to convert: {}", e);
-                                    match state {
-                                        JsonState::UniDigVal => state = JsonState::ObjData,
-                                        JsonState::UniDigName => state = JsonState::ObjName,
-                                        _ => unreachable!(),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+                self.comma = false;
+                if self.state == JsonState::ObjName {
+                    self.state = JsonState::ExpNameSep;
+                    Some(Tok::Key)
+                } else {
+                    self.after_value();
+                    Some(Tok::Str)
                 }
             }
-            ',' => {
-                match state {
-                    JsonState::Start => state = JsonState::ErrState,
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    /*JsonState::ArrNumValue => {
-                        arr.push(JsonData::Num(if neg {-num_value} else {num_val}));
-                        state = JsonState::ArrNext
-                    }*/
-                    JsonState::NumValue | JsonState::MantissaValue => {
-                        return (JsonData::Num(if neg { -num_value } else { num_value }), c)
-                    }
-                    JsonState::ExpNumValue => {
-                        if neg {
-                            num_value = -num_value
-                        };
-                        if neg_exp {
-                            exp_val = -exp_val
-                        }
-                        return (JsonData::Num(num_value * 10.0_f64.powf(exp_val)), c);
-                    }
-                    JsonState::ObjExpEnd => state = JsonState::ObjState,
-                    JsonState::ArrState => {
-                        arr.push(parse_fragment(chars).0);
-                        state = JsonState::ArrNext
-                    }
-                    JsonState::ArrNext => {
-                        let fragment = parse_fragment(chars);
-                        arr.push(fragment.0);
-                        match fragment.1 {
-                            ',' => {
-                                loop {
-                                    let fragment = parse_fragment(chars);
-                                    arr.push(fragment.0);
-                                    match fragment.1 {
-                                        ']' => return (JsonData::Arr(arr.clone()), c),
-                                        ',' => continue,
-                                        _ => break,
-                                    }
-                                }
-                                state = JsonState::ArrNext
-                            }
-                            ']' => return (JsonData::Arr(arr.clone()), c),
-                            _ => state = JsonState::ArrState,
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
+            '\\' => {
+                self.state = if self.state == JsonState::ObjName {
+                    JsonState::EscName
+                } else {
+                    JsonState::EscValue
+                };
+                None
+            }
+            _ => {
+                if self.raw.is_none() {
+                    if self.keep {
+                        self.text.push(c)
+                    } else {
+                        self.dropped += c.len_utf8()
                     }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
                 }
+                None
             }
-            't' => match state {
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\t')
-                }
-                JsonState::Start => state = JsonState::BoolT,
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            'r' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::BoolT => state = JsonState::BoolR,
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\r')
-                }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            'u' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::BoolR => state = JsonState::BoolU,
-                JsonState::NulN => state = JsonState::NulU,
-
-                JsonState::EscName => {
-                    dig_inx.clear();
-                    state = JsonState::UniDigName
-                }
-                JsonState::EscValue => {
-                    dig_inx.clear();
-                    state = JsonState::UniDigVal
-                }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            'U' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-
-                JsonState::EscName => {
-                    dig_inx.clear();
-                    state = JsonState::UniDigName
+        }
+    }
+
+    fn escape(&mut self, c: char) -> Option<Tok> {
+        let name = self.state == JsonState::EscName;
+        if c == 'u' || c == 'U' || c == 'x' && self.options.json5 {
+            self.dig_inx.clear();
+            if c == 'x' {
+                // two digits of \x complete a code like four digits of \u
+                self.dig_inx.push_str("00")
+            }
+            self.state = if name {
+                JsonState::UniDigName
+            } else {
+                JsonState::UniDigVal
+            };
+            return None;
+        }
+        if let Some((high, at)) = self.surrogate.take()
+            && let Some(err) = self.lone(high, at)
+        {
+            return Some(err);
+        }
+        match c {
+            '"' | '\\' | '/' => self.text.push(c),
+            'b' => self.text.push('\u{8}'),
+            'f' => self.text.push('\u{c}'),
+            'n' => self.text.push('\n'),
+            'r' => self.text.push('\r'),
+            't' => self.text.push('\t'),
+            _ if !self.options.json5 => {
+                self.text.push('\\');
+                self.text.push(c)
+            }
+            'v' => self.text.push('\u{b}'),
+            '0' => self.text.push('\0'),
+            '1'..='9' => return self.malformed(format!("invalid escape \\{c}"), self.here),
+            '\r' => self.cr = true,
+            '\n' | '\u{2028}' | '\u{2029}' => (),
+            _ => self.text.push(c),
+        }
+        self.state = if name {
+            JsonState::ObjName
+        } else {
+            JsonState::ObjData
+        };
+        None
+    }
+
+    fn unicode(&mut self, c: char) -> Option<Tok> {
+        if !c.is_ascii_hexdigit() {
+            return self.error(c);
+        }
+        self.dig_inx.push(c);
+        if self.dig_inx.len() < 4 {
+            return None;
+        }
+        let number = u32::from_str_radix(&self.dig_inx, 16).unwrap_or_default();
+        let low = (0xDC00..0xE000).contains(&number);
+        let high = self.surrogate.take();
+        if let Some((high, at)) = high
+            && !low
+            && let Some(err) = self.lone(high, at)
+        {
+            // the escape isn't consumed, so it's read again after the error
+            self.dig_inx.pop();
+            return Some(err);
+        }
+        self.state = if self.state == JsonState::UniDigName {
+            JsonState::ObjName
+        } else {
+            JsonState::ObjData
+        };
+        if let Some((high, _)) = high
+            && low
+        {
+            let code = 0x10000 + ((high - 0xD800) << 10) + (number - 0xDC00);
+            self.text
+                .push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            return None;
+        }
+        let Some(c) = char::from_u32(number) else {
+            // a surrogate escape is six ASCII chars ending here
+            let at = Position {
+                offset: self.here.offset - 5,
+                column: self.here.column - 5,
+                ..self.here
+            };
+            if number < 0xDC00 {
+                self.surrogate = Some((number, at));
+                return None;
+            }
+            return self.lone(number, at);
+        };
+        self.text.push(c);
+        None
+    }
+
+    /// Applies [LoneSurrogates] to a surrogate escaped at the position
+    fn lone(&mut self, code: u32, at: Position) -> Option<Tok> {
+        match self.options.lone_surrogates {
+            LoneSurrogates::Replace => self.text.push(char::REPLACEMENT_CHARACTER),
+            LoneSurrogates::Escape => self.text.push_str(&format!("\\u{code:04x}")),
+            LoneSurrogates::Error => {
+                // the text is completed as by default if parsing goes on
+                self.text.push(char::REPLACEMENT_CHARACTER);
+                return self.malformed(format!("lone surrogate \\u{code:04x}"), at);
+            }
+        }
+        None
+    }
+
+    fn number(&mut self, c: char) -> Tok {
+        // letters besides an exponent come only in a non-finite number
+        let named = self.options.non_finite
+            && self
+                .text
+                .contains(|c: char| c.is_ascii_alphabetic() && !matches!(c, 'e' | 'E'));
+        let num = if self.options.json5 {
+            json5_number(&self.text)
+        } else if named {
+            match self.text.as_str() {
+                "NaN" => Some(f64::NAN),
+                "Infinity" => Some(f64::INFINITY),
+                "-Infinity" => Some(f64::NEG_INFINITY),
+                _ => None,
+            }
+        } else {
+            self.text.parse::<f64>().ok().filter(|_| is_number(&self.text))
+        };
+        match num {
+            Some(num) => {
+                self.num_mark = self.mark;
+                self.comma = false;
+                self.after_value();
+                Tok::Num(num)
+            }
+            None if self.options.json5 || named => {
+                let what = format!("invalid number {}", self.text);
+                self.malformed(what, self.mark).unwrap()
+            }
+            None => self.error(c).unwrap(),
+        }
+    }
+
+    /// Continues an unquoted JSON5 key, a char which can't be a part of it
+    /// completes the key and is processed as a structural one
+    fn ident(&mut self, c: char) -> [Option<Tok>; 2] {
+        if c.is_alphanumeric() || matches!(c, '$' | '_' | '\u{200c}' | '\u{200d}') {
+            if self.raw.is_none() {
+                if self.keep {
+                    self.text.push(c)
+                } else {
+                    self.dropped += c.len_utf8()
                 }
-                JsonState::EscValue => {
-                    dig_inx.clear();
-                    state = JsonState::UniDigVal
+            }
+            return [None, None];
+        }
+        if let Some(start) = self.raw.take() {
+            self.span = Some((start, self.here.offset))
+        }
+        self.state = JsonState::ExpNameSep;
+        // a char other than a separator is an error reported at itself,
+        // the mark stays at the key
+        let mark = self.mark;
+        let next = self.structural(c);
+        self.mark = mark;
+        [Some(Tok::Key), next]
+    }
+
+    /// Skips a JSON5 comment and returns to the state before it
+    fn comment(&mut self, c: char) -> Option<Tok> {
+        self.state = match (self.state, c) {
+            (JsonState::CommentStart, '/') => JsonState::LineComment,
+            (JsonState::CommentStart, '*') => JsonState::BlockComment,
+            (JsonState::CommentStart, _) => {
+                return self.malformed(format!("invalid comment, / followed by {c:?}"), self.here);
+            }
+            (JsonState::LineComment, '\n' | '\r' | '\u{2028}' | '\u{2029}') => self.resume,
+            (JsonState::LineComment, _) => JsonState::LineComment,
+            (JsonState::BlockComment | JsonState::BlockCommentEnd, '*') => JsonState::BlockCommentEnd,
+            (JsonState::BlockCommentEnd, '/') => self.resume,
+            _ => JsonState::BlockComment,
+        };
+        None
+    }
+
+    fn literal(&mut self, c: char) -> Option<Tok> {
+        let (next, tok) = match (&self.state, c) {
+            (JsonState::BoolT, 'r') => (JsonState::BoolR, None),
+            (JsonState::BoolR, 'u') => (JsonState::BoolU, None),
+            (JsonState::BoolU, 'e') => (JsonState::Start, Some(Tok::Bool(true))),
+            (JsonState::BoolF, 'a') => (JsonState::BoolA, None),
+            (JsonState::BoolA, 'l') => (JsonState::BoolL, None),
+            (JsonState::BoolL, 's') => (JsonState::BoolS, None),
+            (JsonState::BoolS, 'e') => (JsonState::Start, Some(Tok::Bool(false))),
+            (JsonState::NulN, 'u') => (JsonState::NulU, None),
+            (JsonState::NulU, 'l') => (JsonState::NulL, None),
+            (JsonState::NulL, 'l') => (JsonState::Start, Some(Tok::Null)),
+            _ => return self.error(c),
+        };
+        if tok.is_some() {
+            self.comma = false;
+            self.after_value()
+        } else {
+            self.state = next
+        }
+        tok
+    }
+}
+
+/// Returns an index of the first `"` or `\` in the bytes or their length
+///
+/// The bytes are examined by eight at once as in `memchr`.
+fn find_quote(bytes: &[u8]) -> usize {
+    const LO: u64 = 0x0101_0101_0101_0101;
+    const HI: u64 = 0x8080_8080_8080_8080;
+    const QUOTE: u64 = LO * b'"' as u64;
+    const SLASH: u64 = LO * b'\\' as u64;
+    let mut pos = 0;
+    for word in bytes.chunks_exact(8) {
+        let word = u64::from_le_bytes(word.try_into().unwrap());
+        let (quote, slash) = (word ^ QUOTE, word ^ SLASH);
+        let found = (quote.wrapping_sub(LO) & !quote | slash.wrapping_sub(LO) & !slash) & HI;
+        if found != 0 {
+            return pos + (found.trailing_zeros() / 8) as usize;
+        }
+        pos += 8
+    }
+    pos + bytes[pos..]
+        .iter()
+        .position(|&b| b == b'"' || b == b'\\')
+        .unwrap_or(bytes.len() - pos)
+}
+
+/// Checks a number literal against JSON grammar
+fn is_number(num: &str) -> bool {
+    let num = num.strip_prefix('-').unwrap_or(num).as_bytes();
+    let digits = |from: usize| num[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let int = digits(0);
+    if int == 0 || int > 1 && num[0] == b'0' {
+        return false;
+    }
+    let mut pos = int;
+    if num.get(pos) == Some(&b'.') {
+        let frac = digits(pos + 1);
+        if frac == 0 {
+            return false;
+        }
+        pos += frac + 1
+    }
+    if matches!(num.get(pos), Some(b'e' | b'E')) {
+        pos += 1;
+        if matches!(num.get(pos), Some(b'-' | b'+')) {
+            pos += 1
+        }
+        let exp = digits(pos);
+        if exp == 0 {
+            return false;
+        }
+        pos += exp
+    }
+    pos == num.len()
+}
+
+/// Parses a JSON5 number literal, it can have a sign, be hexadecimal,
+/// `Infinity` or `NaN`, and omit digits before or after the point
+fn json5_number(num: &str) -> Option<f64> {
+    let (sign, body) = match num.as_bytes().first() {
+        Some(b'-') => (-1.0, &num[1..]),
+        Some(b'+') => (1.0, &num[1..]),
+        _ => (1.0, num),
+    };
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    let value = match body {
+        "Infinity" => f64::INFINITY,
+        "NaN" => f64::NAN,
+        _ => match body.strip_prefix("0x").or_else(|| body.strip_prefix("0X")) {
+            Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => hex
+                .chars()
+                .fold(0.0, |num, dig| num * 16.0 + dig.to_digit(16).unwrap_or_default() as f64),
+            Some(_) => return None,
+            None => {
+                let (mantissa, exp) = match body.split_once(['e', 'E']) {
+                    Some((mantissa, exp)) => (mantissa, Some(exp)),
+                    None => (body, None),
+                };
+                let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+                if int.is_empty() && frac.is_empty()
+                    || !digits(int)
+                    || !digits(frac)
+                    || int.len() > 1 && int.starts_with('0')
+                {
+                    return None;
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            'e' => {
-                match state {
-                    JsonState::Start => state = JsonState::ErrState,
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::MantissaValue | JsonState::NumValue => {
-                        //exp_val = 0.0
-                        state = JsonState::ExpExpValue
+                if let Some(exp) = exp {
+                    let exp = exp.strip_prefix(['-', '+']).unwrap_or(exp);
+                    if exp.is_empty() || !digits(exp) {
+                        return None;
                     }
-                    JsonState::BoolU => return (JsonData::Bool(true), c),
-                    JsonState::BoolS => return (JsonData::Bool(false), c),
-                    JsonState::UniDigVal | JsonState::UniDigName => {
-                        dig_inx.push(c);
-                        if dig_inx.len() == 4 {
-                            match u32::from_str_radix(&dig_inx, 16) {
-                                Ok(number) => {
-                                    //println!("The number is: {}", number);
-                                    match std::char::from_u32(number) {
-                                        Some(character) => {
-                                            //println!("The character is: {}", character);
-                                            match state {
-                                                JsonState::UniDigVal => {
-                                                    field_value.push(character);
-                                                    state = JsonState::ObjData
-                                                }
-                                                JsonState::UniDigName => {
-                                                    field_name.push(character);
-                                                    state = JsonState::ObjName
-                                                }
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                        None => {
-                                            //eprintln!("Error: Invalid Unicode scalar value!");
-                                            if !surrogate_first {
-                                                surrogates[1] = number as u16;
-                                                match char::decode_utf16(surrogates).next() {
-                                                    Some(Ok(character)) => match state {
-                                                        JsonState::UniDigVal => {
-                                                            field_value.push(character)
-                                                        }
-                                                        JsonState::UniDigName => {
-                                                            field_name.push(character)
-                                                        }
-                                                        _ => unreachable!(),
-                                                    },
-                                                    Some(Err(_)) => match state {
-                                                        JsonState::UniDigVal => field_value
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        JsonState::UniDigName => field_name
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        _ => unreachable!(),
-                                                    },
-                                                    None => unreachable!(),
-                                                }
-                                            } else {
-                                                surrogates[0] = number as u16;
-                                            }
-                                            surrogate_first = !surrogate_first;
-                                            match state {
-                                                JsonState::UniDigVal => state = JsonState::ObjData,
-                                                JsonState::UniDigName => state = JsonState::ObjName,
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to convert: {}", e);
-                                    match state {
-                                        JsonState::UniDigVal => state = JsonState::ObjData,
-                                        JsonState::UniDigName => state = JsonState::ObjName,
-                                        _ => unreachable!(),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
                 }
+                body.parse().ok()?
             }
-            'f' => {
-                match state {
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::Start => state = JsonState::BoolF,
-                    JsonState::UniDigVal | JsonState::UniDigName => {
-                        dig_inx.push(c);
-                        if dig_inx.len() == 4 {
-                            match u32::from_str_radix(&dig_inx, 16) {
-                                Ok(number) => {
-                                    //println!("The number is: {}", number);
-                                    match std::char::from_u32(number) {
-                                        Some(character) => {
-                                            //println!("The character is: {}", character);
-                                            match state {
-                                                JsonState::UniDigVal => {
-                                                    field_value.push(character);
-                                                    state = JsonState::ObjData
-                                                }
-                                                JsonState::UniDigName => {
-                                                    field_name.push(character);
-                                                    state = JsonState::ObjName
-                                                }
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                        None => {
-                                            //eprintln!("Error: Invalid Unicode scalar value!");
-                                            if !surrogate_first {
-                                                surrogates[1] = number as u16;
-                                                match char::decode_utf16(surrogates).next() {
-                                                    Some(Ok(character)) => match state {
-                                                        JsonState::UniDigVal => {
-                                                            field_value.push(character)
-                                                        }
-                                                        JsonState::UniDigName => {
-                                                            field_name.push(character)
-                                                        }
-                                                        _ => unreachable!(),
-                                                    },
-                                                    Some(Err(_)) => match state {
-                                                        JsonState::UniDigVal => field_value
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        JsonState::UniDigName => field_name
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        _ => unreachable!(),
-                                                    },
-                                                    None => unreachable!(),
-                                                }
-                                            } else {
-                                                surrogates[0] = number as u16;
-                                            }
-                                            surrogate_first = !surrogate_first;
-                                            match state {
-                                                JsonState::UniDigVal => state = JsonState::ObjData,
-                                                JsonState::UniDigName => state = JsonState::ObjName,
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to convert: {}", e);
-                                    match state {
-                                        JsonState::UniDigVal => state = JsonState::ObjData,
-                                        JsonState::UniDigName => state = JsonState::ObjName,
-                                        _ => unreachable!(),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push(12 as char);
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-                }
+        },
+    };
+    Some(sign * value)
+}
+
+/// A value which can gather values of a repeated key
+trait Collect: Sized {
+    fn collect(values: Vec<Self>) -> Self;
+
+    /// Returns values gathered before
+    fn collected(&mut self) -> &mut Vec<Self>;
+}
+
+impl Collect for JsonData {
+    fn collect(values: Vec<Self>) -> Self {
+        JsonData::Arr(values)
+    }
+
+    fn collected(&mut self) -> &mut Vec<Self> {
+        match self {
+            JsonData::Arr(arr) => arr,
+            _ => unreachable!("values are gathered to an array"),
+        }
+    }
+}
+
+impl Collect for JsonRef<'_> {
+    fn collect(values: Vec<Self>) -> Self {
+        JsonRef::Arr(values)
+    }
+
+    fn collected(&mut self) -> &mut Vec<Self> {
+        match self {
+            JsonRef::Arr(arr) => arr,
+            _ => unreachable!("values are gathered to an array"),
+        }
+    }
+}
+
+/// Members of an open object, the key of a member being parsed and what
+/// [DuplicateKeys] needs
+#[derive(Debug)]
+struct Members<K, V> {
+    obj: HashMap<K, V>,
+    key: K,
+    /// Starts of keys, kept for [DuplicateKeys::Error] only
+    starts: Option<HashMap<K, Position>>,
+    /// Keys repeated with [DuplicateKeys::Collect]
+    repeated: Option<HashSet<K>>,
+}
+
+impl<K: Default, V> Default for Members<K, V> {
+    fn default() -> Self {
+        Members {
+            obj: HashMap::new(),
+            key: K::default(),
+            starts: None,
+            repeated: None,
+        }
+    }
+}
+
+impl<K: Default + Hash + Eq + Clone, V: Collect> Members<K, V> {
+    /// Sets the key of the next member, returns where the same key starts
+    /// when it repeats and the policy is [DuplicateKeys::Error]
+    fn key(&mut self, key: K, at: Position, policy: DuplicateKeys) -> Option<Position> {
+        let mut first = None;
+        if policy == DuplicateKeys::Error {
+            let starts = self.starts.get_or_insert_default();
+            first = starts.get(&key).copied();
+            if first.is_none() {
+                starts.insert(key.clone(), at);
             }
-            'a' => {
-                match state {
-                    JsonState::Start => state = JsonState::ErrState,
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::BoolF => state = JsonState::BoolA,
-
-                    JsonState::UniDigVal | JsonState::UniDigName => {
-                        dig_inx.push(c);
-                        if dig_inx.len() == 4 {
-                            match u32::from_str_radix(&dig_inx, 16) {
-                                Ok(number) => {
-                                    //println!("The number is: {}", number);
-                                    match std::char::from_u32(number) {
-                                        Some(character) => {
-                                            //println!("The character is: {}", character);
-                                            match state {
-                                                JsonState::UniDigVal => {
-                                                    field_value.push(character);
-                                                    state = JsonState::ObjData
-                                                }
-                                                JsonState::UniDigName => {
-                                                    field_name.push(character);
-                                                    state = JsonState::ObjName
-                                                }
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                        None => {
-                                            //eprintln!("Error: Invalid Unicode scalar value!");
-                                            if !surrogate_first {
-                                                surrogates[1] = number as u16;
-                                                match char::decode_utf16(surrogates).next() {
-                                                    Some(Ok(character)) => match state {
-                                                        JsonState::UniDigVal => {
-                                                            field_value.push(character)
-                                                        }
-                                                        JsonState::UniDigName => {
-                                                            field_name.push(character)
-                                                        }
-                                                        _ => unreachable!(),
-                                                    },
-                                                    Some(Err(_)) => match state {
-                                                        JsonState::UniDigVal => field_value
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        JsonState::UniDigName => field_name
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        _ => unreachable!(),
-                                                    },
-                                                    None => unreachable!(),
-                                                }
-                                            } else {
-                                                surrogates[0] = number as u16;
-                                            }
-                                            surrogate_first = !surrogate_first;
-                                            match state {
-                                                JsonState::UniDigVal => state = JsonState::ObjData,
-                                                JsonState::UniDigName => state = JsonState::ObjName,
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to convert: {}", e);
-                                    match state {
-                                        JsonState::UniDigVal => state = JsonState::ObjData,
-                                        JsonState::UniDigName => state = JsonState::ObjName,
-                                        _ => unreachable!(),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        field_value.push('\\');
-                        field_value.push(c)
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-                }
+        }
+        self.key = key;
+        first
+    }
+
+    fn insert(&mut self, value: V, policy: DuplicateKeys) {
+        let key = std::mem::take(&mut self.key);
+        match (policy, self.obj.entry(key)) {
+            (_, Entry::Vacant(entry)) => {
+                entry.insert(value);
             }
-            'l' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::BoolA => state = JsonState::BoolL,
-                JsonState::NulU => state = JsonState::NulL,
-                JsonState::NulL => return (JsonData::Null, c),
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
+            (DuplicateKeys::FirstWins, Entry::Occupied(_)) => (),
+            (DuplicateKeys::Collect, Entry::Occupied(mut entry)) => {
+                if self.repeated.get_or_insert_default().insert(entry.key().clone()) {
+                    let first = std::mem::replace(entry.get_mut(), V::collect(Vec::new()));
+                    *entry.get_mut() = V::collect(vec![first, value])
+                } else {
+                    entry.get_mut().collected().push(value)
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
-            's' => match state {
-                JsonState::Start => state = JsonState::ErrState,
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::BoolL => state = JsonState::BoolS,
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
+            }
+            (_, Entry::Occupied(mut entry)) => {
+                entry.insert(value);
+            }
+        }
+    }
+}
+
+/// An open container with already collected members
+#[derive(Debug)]
+enum Frame {
+    Arr(Vec<JsonData>),
+    Obj(Members<String, JsonData>),
+}
+
+/// Assembles [JsonData] from [Lexer] tokens keeping unfinished containers
+/// on an explicit stack
+///
+/// A completed value is moved into its parent container and strings are
+/// taken from the lexer buffer, so nothing is copied while the tree grows.
+#[derive(Debug, Default)]
+struct Builder {
+    stack: Vec<Frame>,
+    /// Errors of [DuplicateKeys::Error] which don't stop recovering parsing
+    errors: Option<Vec<String>>,
+}
+
+impl Builder {
+    /// Accepts a token and returns a top level value when it is completed
+    fn token(&mut self, tok: Tok, lexer: &mut Lexer) -> Option<JsonData> {
+        let policy = lexer.options.duplicate_keys;
+        let value = match tok {
+            Tok::StartObject => {
+                self.stack.push(Frame::Obj(Members::default()));
+                return None;
+            }
+            Tok::StartArray => {
+                self.stack.push(Frame::Arr(Vec::new()));
+                return None;
+            }
+            Tok::Key => {
+                if let Some(Frame::Obj(members)) = self.stack.last_mut()
+                    && let Some(first) = members.key(std::mem::take(&mut lexer.text), lexer.mark, policy)
+                {
+                    let state = lexer.state;
+                    let err = lexer.duplicate(&members.key, first);
+                    let Some(errors) = &mut self.errors else {
+                        return self.token(Tok::Err(err), lexer);
+                    };
+                    errors.push(err);
+                    lexer.state = state
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+                return None;
+            }
+            Tok::EndObject | Tok::EndArray => match self.stack.pop() {
+                Some(Frame::Arr(arr)) => JsonData::Arr(arr),
+                Some(Frame::Obj(members)) => JsonData::Data(members.obj),
+                None => unreachable!("the lexer balances containers"),
             },
-            'n' => match state {
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\n')
+            Tok::Str => JsonData::Text(std::mem::take(&mut lexer.text)),
+            Tok::Num(num) => JsonData::Num(num),
+            Tok::Bool(b) => JsonData::Bool(b),
+            Tok::Null => JsonData::Null,
+            Tok::Err(err) => {
+                self.stack.clear();
+                return Some(JsonData::Err(err));
+            }
+        };
+        self.add(value, policy)
+    }
+
+    /// Moves a completed value into its container, or returns it when it's
+    /// a top level one
+    fn add(&mut self, value: JsonData, policy: DuplicateKeys) -> Option<JsonData> {
+        match self.stack.last_mut() {
+            None => Some(value),
+            Some(Frame::Arr(arr)) => {
+                arr.push(value);
+                None
+            }
+            Some(Frame::Obj(members)) => {
+                members.insert(value, policy);
+                None
+            }
+        }
+    }
+}
+
+/// An open container of [JsonRef] with already collected members
+#[derive(Debug)]
+enum RefFrame<'a> {
+    Arr(Vec<JsonRef<'a>>),
+    Obj(Members<Cow<'a, str>, JsonRef<'a>>),
+}
+
+/// Assembles [JsonRef] same way as [Builder] does for [JsonData]
+#[derive(Debug, Default)]
+struct RefBuilder<'a> {
+    stack: Vec<RefFrame<'a>>,
+}
+
+impl<'a> RefBuilder<'a> {
+    fn token(&mut self, tok: Tok, lexer: &mut Lexer, json: &'a str) -> Option<JsonRef<'a>> {
+        let policy = lexer.options.duplicate_keys;
+        let value = match tok {
+            Tok::StartObject => {
+                self.stack.push(RefFrame::Obj(Members::default()));
+                return None;
+            }
+            Tok::StartArray => {
+                self.stack.push(RefFrame::Arr(Vec::new()));
+                return None;
+            }
+            Tok::Key => {
+                let key = lexer.take_text(json);
+                if let Some(RefFrame::Obj(members)) = self.stack.last_mut()
+                    && let Some(first) = members.key(key, lexer.mark, policy)
+                {
+                    let err = lexer.duplicate(&members.key, first);
+                    return self.token(Tok::Err(err), lexer, json);
                 }
-                JsonState::Start => state = JsonState::NulN,
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+                return None;
+            }
+            Tok::EndObject | Tok::EndArray => match self.stack.pop() {
+                Some(RefFrame::Arr(arr)) => JsonRef::Arr(arr),
+                Some(RefFrame::Obj(members)) => JsonRef::Data(members.obj),
+                None => unreachable!("the lexer balances containers"),
             },
-            'b' | 'c' | 'd' | 'B' | 'C' | 'D' | 'A' | 'F' => {
-                match state {
-                    JsonState::ObjName => field_name.push(c),
-                    JsonState::ObjData => field_value.push(c),
-                    JsonState::UniDigVal | JsonState::UniDigName => {
-                        dig_inx.push(c);
-                        if dig_inx.len() == 4 {
-                            match u32::from_str_radix(&dig_inx, 16) {
-                                Ok(number) => {
-                                    //println!("The number is: {}", number);
-                                    match std::char::from_u32(number) {
-                                        Some(character) => {
-                                            //println!("The character is: {}", character);
-                                            match state {
-                                                JsonState::UniDigVal => {
-                                                    field_value.push(character);
-                                                    state = JsonState::ObjData
-                                                }
-                                                JsonState::UniDigName => {
-                                                    field_name.push(character);
-                                                    state = JsonState::ObjName
-                                                }
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                        None => {
-                                            //eprintln!("Error: Invalid Unicode scalar value!");
-                                            if !surrogate_first {
-                                                surrogates[1] = number as u16;
-                                                match char::decode_utf16(surrogates).next() {
-                                                    Some(Ok(character)) => match state {
-                                                        JsonState::UniDigVal => {
-                                                            field_value.push(character)
-                                                        }
-                                                        JsonState::UniDigName => {
-                                                            field_name.push(character)
-                                                        }
-                                                        _ => unreachable!(),
-                                                    },
-                                                    Some(Err(_)) => match state {
-                                                        JsonState::UniDigVal => field_value
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        JsonState::UniDigName => field_name
-                                                            .push(char::REPLACEMENT_CHARACTER),
-                                                        _ => unreachable!(),
-                                                    },
-                                                    None => unreachable!(),
-                                                }
-                                            } else {
-                                                surrogates[0] = number as u16;
-                                            }
-                                            surrogate_first = !surrogate_first;
-                                            match state {
-                                                JsonState::UniDigVal => state = JsonState::ObjData,
-                                                JsonState::UniDigName => state = JsonState::ObjName,
-                                                _ => unreachable!(),
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to convert: {}", e);
-                                    match state {
-                                        JsonState::UniDigVal => state = JsonState::ObjData,
-                                        JsonState::UniDigName => state = JsonState::ObjName,
-                                        _ => unreachable!(),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    JsonState::EscValue => {
-                        state = JsonState::ObjData;
-                        if c == 'b' {
-                            field_value.push(0x8 as char);
-                        } else {
-                            field_value.push('\\');
-                            field_value.push(c)
-                        }
-                    }
-                    _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
+            Tok::Str => JsonRef::Text(lexer.take_text(json)),
+            Tok::Num(num) => JsonRef::Num(num),
+            Tok::Bool(b) => JsonRef::Bool(b),
+            Tok::Null => JsonRef::Null,
+            Tok::Err(err) => {
+                self.stack.clear();
+                return Some(JsonRef::Err(err));
+            }
+        };
+        match self.stack.last_mut() {
+            None => Some(value),
+            Some(RefFrame::Arr(arr)) => {
+                arr.push(value);
+                None
+            }
+            Some(RefFrame::Obj(members)) => {
+                members.insert(value, policy);
+                None
+            }
+        }
+    }
+}
+
+/// Result of feeding [JsonParser]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parsed {
+    /// Top level values completed by the input so far
+    Values(Vec<JsonData>),
+    /// No value completed yet, more input is required
+    NeedMore,
+}
+
+/// Push style incremental parser
+///
+/// Chunks of JSON text are fed as they arrive, for example from a network
+/// connection, and every completed top level value is returned without
+/// waiting for the rest of the input. The parser never blocks and keeps
+/// its state in own stack, so it can be driven from an event loop.
+/// ```
+/// let mut parser = simjson::JsonParser::new();
+/// assert_eq!(parser.feed(r#"{"name":"Mal"#), simjson::Parsed::NeedMore);
+/// let simjson::Parsed::Values(values) = parser.feed(r#"vika"} [1,2]"#) else { panic!() };
+/// assert_eq!(values.len(), 2);
+/// ```
+/// A syntax error is returned as [JsonData::Err] and the rest of the
+/// input is ignored.
+#[derive(Debug, Default)]
+pub struct JsonParser {
+    lexer: Lexer,
+    builder: Builder,
+}
+
+impl JsonParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_options(options: &ParseOptions) -> Self {
+        JsonParser {
+            lexer: Lexer::with_options(options),
+            builder: Builder::default(),
+        }
+    }
+
+    /// Parses a next chunk of input
+    pub fn feed(&mut self, chunk: &str) -> Parsed {
+        let mut values = Vec::new();
+        let mut at = 0;
+        while at < chunk.len() {
+            for tok in self.lexer.scan(chunk, &mut at).into_iter().flatten() {
+                if let Some(value) = self.builder.token(tok, &mut self.lexer) {
+                    values.push(value)
                 }
             }
-            _ => match state {
-                JsonState::Start => state = JsonState::ErrState,
-
-                JsonState::ObjName => field_name.push(c),
-                JsonState::ObjData => field_value.push(c),
-                JsonState::EscName => {
-                    state = JsonState::ObjName;
-                    field_name.push('\\');
-                    field_name.push(c)
+        }
+        if values.is_empty() {
+            Parsed::NeedMore
+        } else {
+            Parsed::Values(values)
+        }
+    }
+
+    /// Tells that no more input will come and returns the rest of values
+    ///
+    /// A top level number can't be completed until the end of input is
+    /// known, and a truncated document is reported as [JsonData::Err].
+    pub fn finish(&mut self) -> Vec<JsonData> {
+        let mut values = Vec::new();
+        if let Some(tok) = self.lexer.finish()
+            && let Some(value) = self.builder.token(tok, &mut self.lexer)
+        {
+            values.push(value)
+        }
+        self.lexer = Lexer::with_options(&self.lexer.options);
+        self.builder = Builder::default();
+        values
+    }
+
+    /// Tells if a value is partially parsed
+    pub fn is_pending(&self) -> bool {
+        !self.lexer.is_idle()
+    }
+}
+
+/// A parsing event of [JsonReader]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    StartObject,
+    Key(&'a str),
+    StartArray,
+    String(&'a str),
+    Number(f64),
+    Bool(bool),
+    Null,
+    EndArray,
+    EndObject,
+    Err(String),
+}
+
+/// Pull style (SAX like) reader producing [Event]s
+///
+/// No `JsonData` tree is built, so documents of any size can be processed
+/// in a constant memory besides the nesting stack. Several top level values
+/// can follow each other, the reader reports them in sequence.
+/// ```
+/// use simjson::{Event, JsonReader};
+/// let mut reader = JsonReader::new(r#"{"skip":[1,[2]],"name":"Malvika"}"#.chars());
+/// assert_eq!(reader.next_event().unwrap().0, Event::StartObject);
+/// assert_eq!(reader.next_event().unwrap().0, Event::Key("skip"));
+/// reader.skip_value();
+/// assert_eq!(reader.next_event().unwrap().0, Event::Key("name"));
+/// assert_eq!(reader.next_event().unwrap().0, Event::String("Malvika"));
+/// ```
+pub struct JsonReader<I> {
+    chars: I,
+    lexer: Lexer,
+    pending: Option<(Tok, Position)>,
+    done: bool,
+}
+
+impl<I> JsonReader<I>
+where
+    I: Iterator<Item = char>,
+{
+    pub fn new(chars: I) -> Self {
+        Self::with_options(chars, &ParseOptions::default())
+    }
+
+    pub fn with_options(chars: I, options: &ParseOptions) -> Self {
+        JsonReader {
+            chars,
+            lexer: Lexer::with_options(options),
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Returns a next event and its start position, or `None` at the end
+    /// of input or after an error
+    pub fn next_event(&mut self) -> Option<(Event<'_>, Position)> {
+        let (tok, pos) = self.next_tok()?;
+        let event = match tok {
+            Tok::StartObject => Event::StartObject,
+            Tok::EndObject => Event::EndObject,
+            Tok::StartArray => Event::StartArray,
+            Tok::EndArray => Event::EndArray,
+            Tok::Key => Event::Key(&self.lexer.text),
+            Tok::Str => Event::String(&self.lexer.text),
+            Tok::Num(num) => Event::Number(num),
+            Tok::Bool(b) => Event::Bool(b),
+            Tok::Null => Event::Null,
+            Tok::Err(err) => Event::Err(err),
+        };
+        Some((event, pos))
+    }
+
+    /// Skips a next value including all nested values when it is
+    /// an object or an array, call it after [Event::Key] to skip the member
+    ///
+    /// At the end of a container nothing is skipped, and an error is
+    /// left to be returned by [JsonReader::next_event].
+    pub fn skip_value(&mut self) {
+        self.skip(0)
+    }
+
+    /// Skips the rest of the innermost open object or array including
+    /// its end event
+    pub fn skip_container(&mut self) {
+        self.skip(1)
+    }
+
+    /// Returns a number of objects and arrays currently open
+    pub fn depth(&self) -> usize {
+        self.lexer.nest.len()
+    }
+
+    fn skip(&mut self, mut depth: usize) {
+        self.lexer.keep = false;
+        while let Some((tok, pos)) = self.next_tok() {
+            match tok {
+                Tok::StartObject | Tok::StartArray => depth += 1,
+                Tok::EndObject | Tok::EndArray if depth > 0 => depth -= 1,
+                Tok::Key => continue,
+                Tok::EndObject | Tok::EndArray | Tok::Err(_) => {
+                    self.pending = Some((tok, pos));
+                    break;
                 }
-                JsonState::EscValue => {
-                    state = JsonState::ObjData;
-                    field_value.push('\\');
-                    field_value.push(c)
+                _ => (),
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+        self.lexer.keep = true
+    }
+
+    fn next_tok(&mut self) -> Option<(Tok, Position)> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+        if self.done {
+            return None;
+        }
+        for c in self.chars.by_ref() {
+            let [first, second] = self.lexer.push(c);
+            if let Some(tok) = first {
+                let pos = self.lexer.position(&tok);
+                if let Some(tok) = second {
+                    let pos = self.lexer.position(&tok);
+                    self.pending = Some((tok, pos))
                 }
-                _ => error!("state {state:?} for {c} at {char_pos}:{char_line}"),
-            },
+                self.done = matches!(tok, Tok::Err(_));
+                return Some((tok, pos));
+            }
         }
+        self.done = true;
+        let tok = self.lexer.finish()?;
+        let pos = self.lexer.position(&tok);
+        Some((tok, pos))
     }
-    (JsonData::None, char::from_u32(0).unwrap())
 }
 
 pub fn esc_quotes(jstr: String) -> String {
@@ -1077,41 +1853,3 @@ pub fn esc_quotes(jstr: String) -> String {
     }
     res
 }
-
-#[cfg(test)]
-use JsonData::{Arr, Data};
-#[cfg(test)]
-fn main() {
-    let res = parse("[{\"name\":\"malina\", \"age\":19},{}, 45.8]");
-    println! {"{res:?}"}
-    let res = parse("{\"name\":\"calina\", \"age\":39, \"husband\":{\"name\":\"Josef\", \"age\":65}, \"mid\":\"A\", \"kids\":[\"jef\", \"ruth\"], \"port\":400}");
-    println! {"{res:?}"}
-    let res = parse("[300,-42.6,1.562e45, 0.56e3]");
-    println! {"{res:?}"}
-    let res = parse(r#"[0.56e-2,5,32,54.08,-5.6,null,false,true,70e12,1.2E03]"#);
-    println! {"{res:?}"}
-    let res = parse(
-        r#"[[0,5],[3,0.2],[{"a\"":"70" ,"b":"28", "S":true},{"c":"d\"","Mar":false,"x":[4, 8 ] }]]"#,
-    );
-    println! {"{res:?}"}
-    let json_str = r#"{"simple":"json"}
-         {"another":true} ["again","stop"]
-         {"not again":false}
-"#;
-    let mut chars = json_str.chars();
-    loop {
-        let res = parse_fragment(&mut chars);
-        let json = match res.0 {
-            Data(json) => println!("{json:?}"),
-            Arr(json) => println!("{json:?}"),
-            JsonData::None => {
-                println!("end of fragments");
-                break;
-            }
-            _ => {
-                eprintln!("invalid json {:?}", res.0);
-                break;
-            }
-        };
-    }
-}
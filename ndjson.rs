@@ -0,0 +1,119 @@
+//! Parallel parsing of JSON Lines (NDJSON) text
+use std::{num::NonZeroUsize, thread};
+
+use crate::{Builder, JsonData, Lexer, ParseOptions, Position, Tok};
+
+/// Parses JSON Lines text, one value per line, using several threads
+///
+/// The text is split at line boundaries into a chunk per thread, and
+/// values are returned in the original order along with their line
+/// numbers counted from 1. Blank lines are skipped, an invalid line gives
+/// [JsonData::Err] without affecting others, as does text other than
+/// whitespace following the value of a line. Zero `threads` means
+/// the available parallelism.
+/// ```
+/// let lines = simjson::parse_lines("{\"a\":1}\n\n[2]\n", 2);
+/// assert_eq!(lines.len(), 2);
+/// assert_eq!(lines[1].0, 3);
+/// ```
+pub fn parse_lines(text: &str, threads: usize) -> Vec<(usize, JsonData)> {
+    parse_lines_with(text, threads, &ParseOptions::default())
+}
+
+pub fn parse_lines_with(
+    text: &str,
+    threads: usize,
+    options: &ParseOptions,
+) -> Vec<(usize, JsonData)> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
+    let mut chunks = Vec::with_capacity(threads);
+    let mut rest = text;
+    while !rest.is_empty() {
+        let size = (text.len() / threads).max(1);
+        let end = match rest.as_bytes().iter().skip(size).position(|&b| b == b'\n') {
+            Some(nl) => size + nl + 1,
+            None => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail
+    }
+    let parsed: Vec<(usize, Vec<(usize, JsonData)>)> = thread::scope(|scope| {
+        let workers: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| scope.spawn(move || parse_chunk(chunk, options)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("parsing doesn't panic"))
+            .collect()
+    });
+    let mut res = Vec::with_capacity(parsed.iter().map(|(_, values)| values.len()).sum());
+    let mut line = 0;
+    for (lines, values) in parsed {
+        res.extend(values.into_iter().map(|(num, value)| (line + num, value)));
+        line += lines
+    }
+    res
+}
+
+/// Parses the lines of a chunk and returns their count
+fn parse_chunk(chunk: &str, options: &ParseOptions) -> (usize, Vec<(usize, JsonData)>) {
+    let mut values = Vec::new();
+    let mut lines = 0;
+    for line in chunk.split_terminator('\n') {
+        lines += 1;
+        if !line.trim().is_empty() {
+            values.push((lines, parse_line(line, options)))
+        }
+    }
+    (lines, values)
+}
+
+/// Parses a line holding one value followed by whitespace or comments only
+fn parse_line(line: &str, options: &ParseOptions) -> JsonData {
+    let mut lexer = Lexer::with_options(options);
+    let mut builder = Builder::default();
+    let mut value = None;
+    let mut at = 0;
+    while at < line.len() && value.is_none() {
+        for tok in lexer.scan(line, &mut at).into_iter().flatten() {
+            if value.is_some() {
+                // a number is completed by a char starting the next token
+                return after_value(lexer.last);
+            }
+            value = builder.token(tok, &mut lexer)
+        }
+    }
+    let value = match value {
+        Some(JsonData::Err(err)) => return JsonData::Err(err),
+        Some(value) => value,
+        None => {
+            return match lexer.finish() {
+                Some(tok) => builder.token(tok, &mut lexer).unwrap_or(JsonData::None),
+                None => JsonData::None,
+            };
+        }
+    };
+    for c in line[at..].chars() {
+        let here = lexer.here;
+        let [tok, _] = lexer.push(c);
+        if tok.is_some() || !lexer.is_blank() {
+            return after_value(here);
+        }
+    }
+    match lexer.finish() {
+        Some(Tok::Err(err)) => JsonData::Err(err),
+        _ => value,
+    }
+}
+
+fn after_value(at: Position) -> JsonData {
+    JsonData::Err(format!(
+        "an error: text after the value at {}:{}",
+        at.column, at.line
+    ))
+}
@@ -0,0 +1,629 @@
+//! Tolerant parsing reporting all errors of a document and partial
+//! parsing of a truncated one
+use std::{collections::HashSet, mem};
+
+use crate::diagnostic::push_component;
+use crate::{Builder, Frame, JsonData, JsonState, Lexer, Nest, ParseOptions, Position, Tok};
+
+/// Parses a `&str` to a best-effort `JsonData` along with all errors of it
+///
+/// Parsing goes on after an error by repairing the input the way it was
+/// most likely meant: a missing comma or colon is assumed, a missing or
+/// broken value becomes the placeholder [JsonData::None], a stray char is
+/// skipped, a mismatched bracket closes the containers inside the matching
+/// one and everything open is closed at the end of input. A string running
+/// over a line break is closed at the end of its first line when it would
+/// run to the end of input or an error follows it.
+/// One error is reported per place, chars skipped after an error aren't
+/// reported again, and text following the top level value is an error too.
+/// A key repeated with [crate::DuplicateKeys::Error] is reported and its
+/// later value is kept, an exceeded limit of [ParseOptions] stops parsing.
+/// ```
+/// let (json, errors) = simjson::parse_tolerant(r#"{"a":[1 2,], "b": tru}"#);
+/// let simjson::JsonData::Data(obj) = &json else { panic!() };
+/// assert_eq!(obj["a"], simjson::parse("[1,2]"));
+/// assert_eq!(obj["b"], simjson::JsonData::None);
+/// assert_eq!(errors, [
+///     "an error: expected ',' or ']' after array element, found '2' at 9:1",
+///     "an error: expected a value after ',', found ']' at 11:1",
+///     "an error: expected true, found '}' at 22:1",
+/// ]);
+/// ```
+pub fn parse_tolerant(json: &str) -> (JsonData, Vec<String>) {
+    parse_tolerant_with(json, &ParseOptions::default())
+}
+
+/// Parses a `&str` tolerating errors and applying the specified options
+pub fn parse_tolerant_with(json: &str, options: &ParseOptions) -> (JsonData, Vec<String>) {
+    Recovery::new(options).run(json)
+}
+
+/// Parses a prefix of JSON text to `JsonData` as if everything open at
+/// its end was closed, along with JSON Pointers of incomplete values
+///
+/// It's meant for rendering JSON while it arrives, as from a generator
+/// writing it token by token. An open string gives the text so far,
+/// a number is complete only when something follows it, a literal cut
+/// short and a value of a member which has a key only give
+/// the placeholder [JsonData::None]. Incomplete values, including open
+/// arrays and objects, are listed innermost first, a complete document
+/// gives none. An error in the prefix is returned as by [crate::parse]
+/// with no incomplete values.
+/// ```
+/// use simjson::JsonData;
+/// let (json, incomplete) = simjson::parse_partial(r#"{"done":[1,2],"items":[{"name":"Mal"#);
+/// let JsonData::Data(obj) = &json else { panic!() };
+/// assert_eq!(obj["items"], simjson::parse(r#"[{"name":"Mal"}]"#));
+/// assert_eq!(incomplete, ["/items/0/name", "/items/0", "/items", ""]);
+/// assert_eq!(simjson::parse_partial("[1, 2"), (simjson::parse("[1, 2]"), vec!["/1".to_string(), "".to_string()]));
+/// assert_eq!(simjson::parse_partial("[1, 2]"), (simjson::parse("[1, 2]"), vec![]));
+/// ```
+pub fn parse_partial(json: &str) -> (JsonData, Vec<String>) {
+    parse_partial_with(json, &ParseOptions::default())
+}
+
+/// Parses a prefix of JSON text applying the specified options, as
+/// [parse_partial]
+pub fn parse_partial_with(json: &str, options: &ParseOptions) -> (JsonData, Vec<String>) {
+    let mut lexer = Lexer::with_options(options);
+    let mut builder = Builder::default();
+    let mut at = 0;
+    while at < json.len() {
+        for tok in lexer.scan(json, &mut at).into_iter().flatten() {
+            if let Some(value) = builder.token(tok, &mut lexer) {
+                return (value, Vec::new());
+            }
+        }
+    }
+    let mut recovery = Recovery {
+        lexer,
+        builder,
+        incomplete: Some(Incomplete::default()),
+        ..Recovery::new(options)
+    };
+    recovery.close_all();
+    let mut incomplete = recovery.incomplete.unwrap_or_default().pointers;
+    match recovery.value {
+        Some(JsonData::Err(err)) => (JsonData::Err(err), Vec::new()),
+        Some(value) => (value, incomplete),
+        None => {
+            incomplete.push(String::new());
+            (JsonData::None, incomplete)
+        }
+    }
+}
+
+/// What to do with a char after repairing the state
+enum Repair {
+    /// Process the char again
+    Again,
+    /// Go on with a next char
+    Skip,
+}
+
+/// Parsing state at the first line break of a string, where the string
+/// is cut when it turns out unterminated
+struct LineBreak {
+    at: usize,
+    lexer: Lexer,
+    quiet: bool,
+    errors: usize,
+}
+
+/// A completed string running over a line break, it's passed to
+/// the builder when a next char is accepted
+struct Held {
+    tok: Tok,
+    text: String,
+    mark: Position,
+    line_break: LineBreak,
+}
+
+struct Recovery {
+    lexer: Lexer,
+    builder: Builder,
+    value: Option<JsonData>,
+    /// Errors are suppressed until a char is accepted after an error
+    quiet: bool,
+    /// Parsing is over after a limit or text after the value
+    stop: bool,
+    /// Offsets of line breaks ending unterminated strings
+    cuts: HashSet<usize>,
+    /// The first line break of the string being parsed
+    line_break: Option<LineBreak>,
+    held: Option<Held>,
+    /// Where parsing goes on after cutting a string
+    restart: Option<usize>,
+    /// Values completed at the end of input by [parse_partial]
+    incomplete: Option<Incomplete>,
+}
+
+/// JSON Pointers of values completed at the end of input
+///
+/// Containers are closed from the innermost one, and a container below
+/// it doesn't change meanwhile, so components of those containers are
+/// kept in a prefix truncated as they close.
+#[derive(Debug, Default)]
+struct Incomplete {
+    pointers: Vec<String>,
+    prefix: String,
+    /// Lengths of the prefix after every kept component
+    ends: Vec<usize>,
+}
+
+impl Incomplete {
+    /// Adds a pointer of a next value of the container at the depth
+    fn push(&mut self, stack: &[Frame], depth: usize) {
+        let fixed = stack.len().saturating_sub(1);
+        self.ends.truncate(fixed);
+        self.prefix.truncate(self.ends.last().copied().unwrap_or(0));
+        for frame in &stack[self.ends.len()..fixed.min(depth)] {
+            push_component(&mut self.prefix, frame);
+            self.ends.push(self.prefix.len())
+        }
+        let pointer = match depth.checked_sub(1) {
+            None => String::new(),
+            Some(inx) if inx < fixed => self.prefix[..self.ends[inx]].to_string(),
+            Some(_) => {
+                let mut pointer = self.prefix.clone();
+                push_component(&mut pointer, &stack[fixed]);
+                pointer
+            }
+        };
+        self.pointers.push(pointer)
+    }
+}
+
+impl Recovery {
+    fn new(options: &ParseOptions) -> Self {
+        Recovery {
+            lexer: Lexer::with_options(options),
+            builder: Builder {
+                errors: Some(Vec::new()),
+                ..Default::default()
+            },
+            value: None,
+            quiet: false,
+            stop: false,
+            cuts: HashSet::new(),
+            line_break: None,
+            held: None,
+            restart: None,
+            incomplete: None,
+        }
+    }
+
+    /// Returns the value and errors
+    ///
+    /// A string is cut by going back to its first line break, so the text
+    /// after it is parsed again once.
+    fn run(mut self, json: &str) -> (JsonData, Vec<String>) {
+        let mut at = 0;
+        loop {
+            while let Some(c) = json[at..].chars().next() {
+                if self.stop {
+                    break;
+                }
+                at += c.len_utf8();
+                if self.value.is_some() {
+                    self.trailing(c)
+                } else {
+                    self.char(c)
+                }
+                if let Some(restart) = self.restart.take() {
+                    at = restart
+                }
+            }
+            if !self.stop
+                && is_string(self.lexer.state)
+                && let Some(line_break) = self.line_break.take()
+            {
+                at = line_break.at;
+                self.cut(line_break);
+                self.restart = None;
+                continue;
+            }
+            break;
+        }
+        if let Some(held) = self.held.take() {
+            self.release(held)
+        }
+        if !self.stop {
+            if self.value.is_none() {
+                let state = self.lexer.state;
+                match self.lexer.finish() {
+                    Some(Tok::Err(err)) => {
+                        self.quiet = false;
+                        self.report(err);
+                        self.lexer.state = state
+                    }
+                    Some(tok) => self.token(tok),
+                    None => (),
+                }
+            } else if let Some(Tok::Err(err)) = self.lexer.finish() {
+                self.quiet = false;
+                self.report(err)
+            }
+        }
+        self.close_all();
+        let errors = self.builder.errors.take().unwrap_or_default();
+        (self.value.unwrap_or(JsonData::None), errors)
+    }
+
+    /// Goes back to the line break closing the string there
+    fn cut(&mut self, line_break: LineBreak) {
+        self.cuts.insert(line_break.at);
+        self.lexer = line_break.lexer;
+        self.quiet = line_break.quiet;
+        if let Some(errors) = &mut self.builder.errors {
+            errors.truncate(line_break.errors)
+        }
+        self.line_break = None;
+        self.restart = Some(line_break.at)
+    }
+
+    /// Passes a held string to the builder as if it came just now
+    fn release(&mut self, held: Held) {
+        let text = mem::replace(&mut self.lexer.text, held.text);
+        let mark = mem::replace(&mut self.lexer.mark, held.mark);
+        self.token(held.tok);
+        self.lexer.text = text;
+        self.lexer.mark = mark
+    }
+
+    fn char(&mut self, c: char) {
+        for attempt in 0..8 {
+            if matches!(self.lexer.state, JsonState::ObjData | JsonState::ObjName)
+                && self.cuts.contains(&self.lexer.here.offset)
+            {
+                let at = self.lexer.mark;
+                self.report(format!(
+                    "an error: unterminated string at {}:{}",
+                    at.column, at.line
+                ));
+                if let Some(tok) = self.lexer.string(self.lexer.quote) {
+                    self.token(tok)
+                }
+            }
+            let (state, here) = (self.lexer.state, self.lexer.here);
+            if matches!(c, '\n' | '\r')
+                && matches!(state, JsonState::ObjData | JsonState::ObjName)
+                && self.line_break.is_none()
+            {
+                self.line_break = Some(LineBreak {
+                    at: here.offset,
+                    lexer: self.lexer.clone(),
+                    quiet: self.quiet,
+                    errors: self.builder.errors.as_ref().map_or(0, Vec::len),
+                })
+            }
+            let err = match self.lexer.push(c) {
+                [Some(Tok::Err(err)), _] => Some((err, state)),
+                // the char completing a number or a key is wrong after it
+                [Some(tok), Some(Tok::Err(err))] => {
+                    let key = tok == Tok::Key;
+                    self.token(tok);
+                    if key {
+                        self.lexer.state = JsonState::ExpNameSep
+                    } else {
+                        self.lexer.after_value()
+                    }
+                    Some((err, self.lexer.state))
+                }
+                toks => {
+                    if !matches!(c, ' ' | '\t' | '\r' | '\n')
+                        && let Some(held) = self.held.take()
+                    {
+                        self.release(held)
+                    }
+                    for tok in toks.into_iter().flatten() {
+                        if self.value.is_some() {
+                            return self.extra(here);
+                        }
+                        // text after a top level string is reported as such
+                        if matches!(tok, Tok::Str | Tok::Key)
+                            && !self.builder.stack.is_empty()
+                            && let Some(line_break) = self.line_break.take()
+                        {
+                            self.held = Some(Held {
+                                tok,
+                                text: mem::take(&mut self.lexer.text),
+                                mark: self.lexer.mark,
+                                line_break,
+                            });
+                            continue;
+                        }
+                        self.token(tok)
+                    }
+                    if self.value.is_some() && !self.lexer.is_blank() {
+                        return self.extra(here);
+                    }
+                    None
+                }
+            };
+            let Some((err, state)) = err else {
+                self.quiet = false;
+                if !is_string(self.lexer.state) {
+                    self.line_break = None
+                }
+                return;
+            };
+            // an error right after a string running over a line break
+            if let Some(held) = self.held.take() {
+                return self.cut(held.line_break);
+            }
+            if self.value.is_some() {
+                return self.extra(here);
+            }
+            if self.lexer.limited {
+                self.quiet = false;
+                self.report(err);
+                self.stop = true;
+                return;
+            }
+            self.report(err);
+            // a lone surrogate is replaced and the string goes on, with
+            // the char unless the lexer took it before the error
+            if let Some(fault) = &self.lexer.fault
+                && fault.what.starts_with("lone surrogate")
+            {
+                let taken = fault.state != state;
+                self.lexer.state = fault.state;
+                if taken || attempt == 7 {
+                    return;
+                }
+                self.lexer.here = here;
+                continue;
+            }
+            match self.repair(state, c) {
+                Repair::Again if attempt < 7 => self.lexer.here = here,
+                _ => return,
+            }
+        }
+    }
+
+    /// Accepts only whitespace and comments after the top level value
+    fn trailing(&mut self, c: char) {
+        let here = self.lexer.here;
+        let [tok, _] = self.lexer.push(c);
+        if tok.is_some() || !self.lexer.is_blank() {
+            self.extra(here)
+        }
+    }
+
+    /// Reports text starting at the position after the top level value
+    fn extra(&mut self, at: Position) {
+        self.report(format!(
+            "an error: text after the value at {}:{}",
+            at.column, at.line
+        ));
+        self.stop = true
+    }
+
+    /// Sets a state to continue from after an error in the state
+    fn repair(&mut self, state: JsonState, c: char) -> Repair {
+        let lexer = &mut self.lexer;
+        let nest = lexer.nest.last().map(|&(nest, _)| nest);
+        match state {
+            JsonState::EscValue | JsonState::EscName => {
+                lexer.text.push(c);
+                lexer.state = string_state(state);
+                Repair::Skip
+            }
+            JsonState::UniDigVal | JsonState::UniDigName => {
+                lexer.dig_inx.clear();
+                lexer.state = string_state(state);
+                Repair::Again
+            }
+            JsonState::CommentStart => {
+                lexer.state = lexer.resume;
+                Repair::Again
+            }
+            JsonState::NegNum
+            | JsonState::NumValue
+            | JsonState::MantissaValue
+            | JsonState::ExpExpValue
+            | JsonState::NegExpNum
+            | JsonState::ExpNumValue => {
+                lexer.text.clear();
+                self.placeholder();
+                Repair::Again
+            }
+            JsonState::BoolT
+            | JsonState::BoolR
+            | JsonState::BoolU
+            | JsonState::BoolF
+            | JsonState::BoolA
+            | JsonState::BoolL
+            | JsonState::BoolS
+            | JsonState::NulN
+            | JsonState::NulU
+            | JsonState::NulL => {
+                self.placeholder();
+                Repair::Again
+            }
+            // a member misses its value
+            JsonState::ExpNameSep if matches!(c, ',' | '}' | ']') => {
+                self.placeholder();
+                Repair::Again
+            }
+            JsonState::Start if nest == Some(Nest::Obj) && matches!(c, ',' | '}' | ']') => {
+                self.placeholder();
+                Repair::Again
+            }
+            JsonState::ArrState if c == ',' => {
+                self.placeholder();
+                Repair::Again
+            }
+            _ if matches!(c, '}' | ']') => self.close(state, c),
+            // a comma is missing
+            JsonState::ArrNext | JsonState::ObjExpEnd if starts_value(c) => {
+                lexer.state = if state == JsonState::ArrNext {
+                    JsonState::ArrState
+                } else {
+                    JsonState::ObjState
+                };
+                Repair::Again
+            }
+            // a colon is missing
+            JsonState::ExpNameSep if starts_value(c) => {
+                lexer.state = JsonState::Start;
+                Repair::Again
+            }
+            _ => {
+                lexer.state = state;
+                Repair::Skip
+            }
+        }
+    }
+
+    /// Repairs a closing bracket which doesn't fit the state
+    fn close(&mut self, state: JsonState, c: char) -> Repair {
+        let want = if c == '}' { Nest::Obj } else { Nest::Arr };
+        self.lexer.state = state;
+        if !self.lexer.nest.iter().any(|&(nest, _)| nest == want) {
+            return Repair::Skip;
+        }
+        if self
+            .lexer
+            .nest
+            .last()
+            .is_some_and(|&(nest, _)| nest == want)
+        {
+            // a trailing comma
+            self.lexer.comma = false;
+            return Repair::Again;
+        }
+        while self
+            .lexer
+            .nest
+            .last()
+            .is_some_and(|&(nest, _)| nest != want)
+        {
+            self.close_one()
+        }
+        Repair::Again
+    }
+
+    /// Closes the innermost container
+    fn close_one(&mut self) {
+        let (state, closer) = match self.lexer.nest.last() {
+            Some((Nest::Obj, _)) => (JsonState::ObjExpEnd, '}'),
+            _ => (JsonState::ArrNext, ']'),
+        };
+        self.lexer.state = state;
+        self.lexer.comma = false;
+        if let Some(tok) = self.lexer.structural(closer) {
+            self.token(tok)
+        }
+    }
+
+    /// Completes everything open at the end of input
+    fn close_all(&mut self) {
+        while self.value.is_none() {
+            let lexer = &mut self.lexer;
+            match lexer.state {
+                JsonState::CommentStart
+                | JsonState::LineComment
+                | JsonState::BlockComment
+                | JsonState::BlockCommentEnd => lexer.state = lexer.resume,
+                state if is_string(state) => {
+                    lexer.state = string_state(state);
+                    if let Some(tok) = lexer.string(lexer.quote) {
+                        self.token(tok)
+                    }
+                }
+                JsonState::ObjIdent => {
+                    let [key, _] = lexer.ident(' ');
+                    if let Some(key) = key {
+                        self.token(key)
+                    }
+                }
+                JsonState::NegNum
+                | JsonState::NumValue
+                | JsonState::MantissaValue
+                | JsonState::ExpExpValue
+                | JsonState::NegExpNum
+                | JsonState::ExpNumValue => match lexer.number(' ') {
+                    Tok::Err(_) => {
+                        lexer.text.clear();
+                        self.placeholder()
+                    }
+                    num => self.token(num),
+                },
+                JsonState::Start if lexer.nest.is_empty() => break,
+                JsonState::ArrState
+                | JsonState::ArrNext
+                | JsonState::ObjState
+                | JsonState::ObjExpEnd => self.close_one(),
+                // a limit stopped parsing, the lexer may be ahead of the builder
+                JsonState::ErrState if self.builder.stack.is_empty() => break,
+                JsonState::ErrState => self.token(Tok::EndArray),
+                _ => self.placeholder(),
+            }
+        }
+    }
+
+    /// Adds a value in place of a missing or broken one
+    fn placeholder(&mut self) {
+        if let Some(incomplete) = &mut self.incomplete {
+            incomplete.push(&self.builder.stack, self.builder.stack.len())
+        }
+        self.lexer.comma = false;
+        self.lexer.after_value();
+        if let Some(value) = self
+            .builder
+            .add(JsonData::None, self.lexer.options.duplicate_keys)
+        {
+            self.value = Some(value)
+        }
+    }
+
+    fn token(&mut self, tok: Tok) {
+        if let Some(incomplete) = &mut self.incomplete {
+            let stack = &self.builder.stack;
+            match tok {
+                Tok::EndObject | Tok::EndArray => {
+                    incomplete.push(stack, stack.len().saturating_sub(1))
+                }
+                Tok::Str | Tok::Num(_) => incomplete.push(stack, stack.len()),
+                _ => (),
+            }
+        }
+        if let Some(value) = self.builder.token(tok, &mut self.lexer) {
+            self.value = Some(value)
+        }
+    }
+
+    fn report(&mut self, err: String) {
+        if !self.quiet {
+            self.builder.errors.get_or_insert_default().push(err)
+        }
+        self.quiet = true
+    }
+}
+
+fn is_string(state: JsonState) -> bool {
+    matches!(
+        state,
+        JsonState::ObjData
+            | JsonState::ObjName
+            | JsonState::EscValue
+            | JsonState::EscName
+            | JsonState::UniDigVal
+            | JsonState::UniDigName
+    )
+}
+
+/// Returns the string state an escape of the state returns to
+fn string_state(state: JsonState) -> JsonState {
+    match state {
+        JsonState::ObjName | JsonState::EscName | JsonState::UniDigName => JsonState::ObjName,
+        _ => JsonState::ObjData,
+    }
+}
+
+/// Tells if the char may start a value or a key
+fn starts_value(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '"' | '\'' | '{' | '[' | '-' | '+' | '.' | '_' | '$')
+}
@@ -0,0 +1,1171 @@
+//! Unit tests of the parser and the serializer
+use std::collections::HashMap;
+
+use super::*;
+use JsonData::{Arr, Bool, Data, Null, Num, Text};
+use JsonState::*;
+
+/// Returns a state of the lexer after every char of the input
+fn states(json: &str) -> Vec<JsonState> {
+    let mut lexer = Lexer::default();
+    json.chars()
+        .map(|c| {
+            lexer.push(c);
+            lexer.state
+        })
+        .collect()
+}
+
+fn obj<const N: usize>(members: [(&str, JsonData); N]) -> JsonData {
+    Data(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+fn text(text: &str) -> JsonData {
+    Text(text.to_string())
+}
+
+fn err(json: &str) -> String {
+    match parse(json) {
+        JsonData::Err(err) => err,
+        other => panic!("{json} parsed to {other:?}"),
+    }
+}
+
+#[test]
+fn literal_states() {
+    assert_eq!(states("true"), [BoolT, BoolR, BoolU, Start]);
+    assert_eq!(states("false"), [BoolF, BoolA, BoolL, BoolS, Start]);
+    assert_eq!(states("null"), [NulN, NulU, NulL, Start]);
+    assert_eq!(states("tx"), [BoolT, ErrState]);
+    assert_eq!(states("fals"), [BoolF, BoolA, BoolL, BoolS]);
+    assert_eq!(states("nil"), [NulN, ErrState, ErrState]);
+}
+
+#[test]
+fn number_states() {
+    assert_eq!(
+        states("-1.5e+3 "),
+        [NegNum, NumValue, MantissaValue, MantissaValue, ExpExpValue, NegExpNum, ExpNumValue, Start]
+    );
+    assert_eq!(states("2E7,"), [NumValue, ExpExpValue, ExpNumValue, ErrState]);
+    assert_eq!(states("[1e-2]"), [ArrState, NumValue, ExpExpValue, NegExpNum, ExpNumValue, Start]);
+    assert_eq!(states("{\"a\":0}"), [ObjState, ObjName, ObjName, ExpNameSep, Start, NumValue, Start]);
+    assert_eq!(states("1.."), [NumValue, MantissaValue, ErrState]);
+    assert_eq!(states("-a"), [NegNum, ErrState]);
+}
+
+#[test]
+fn string_states() {
+    assert_eq!(states("\"a\\n\""), [ObjData, ObjData, EscValue, ObjData, Start]);
+    assert_eq!(
+        states("\"\\u00e9\""),
+        [ObjData, EscValue, UniDigVal, UniDigVal, UniDigVal, UniDigVal, ObjData, Start]
+    );
+    assert_eq!(
+        states("{\"\\u0041\\t\":"),
+        [
+            ObjState, ObjName, EscName, UniDigName, UniDigName, UniDigName, UniDigName, ObjName,
+            EscName, ObjName, ExpNameSep, Start
+        ]
+    );
+    assert_eq!(states("\"\\u00g"), [ObjData, EscValue, UniDigVal, UniDigVal, UniDigVal, ErrState]);
+}
+
+#[test]
+fn container_states() {
+    assert_eq!(states("[ ]"), [ArrState, ArrState, Start]);
+    assert_eq!(states("[1,2]"), [ArrState, NumValue, ArrState, NumValue, Start]);
+    assert_eq!(states("[[],{}]"), [ArrState, ArrState, ArrNext, ArrState, ObjState, ArrNext, Start]);
+    assert_eq!(states("[true]"), [ArrState, BoolT, BoolR, BoolU, ArrNext, Start]);
+    assert_eq!(
+        states("{\"a\":\"b\"}"),
+        [ObjState, ObjName, ObjName, ExpNameSep, Start, ObjData, ObjData, ObjExpEnd, Start]
+    );
+    assert_eq!(
+        states("{\"a\":null,\"b\":[]}"),
+        [
+            ObjState, ObjName, ObjName, ExpNameSep, Start, NulN, NulU, NulL, ObjExpEnd, ObjState,
+            ObjName, ObjName, ExpNameSep, Start, ArrState, ObjExpEnd, Start
+        ]
+    );
+    assert_eq!(states("[1,]"), [ArrState, NumValue, ArrState, ErrState]);
+    assert_eq!(states("{,"), [ObjState, ErrState]);
+    assert_eq!(states("{\"a\" 1"), [ObjState, ObjName, ObjName, ExpNameSep, ExpNameSep, ErrState]);
+    assert_eq!(states("]"), [ErrState]);
+}
+
+#[test]
+fn error_state_is_final() {
+    let mut lexer = Lexer::default();
+    assert!(matches!(lexer.push('}'), [Some(Tok::Err(_)), None]));
+    for c in "[1] {}".chars() {
+        assert_eq!(lexer.push(c), [None, None]);
+        assert_eq!(lexer.state, ErrState)
+    }
+    assert_eq!(lexer.finish(), None)
+}
+
+#[test]
+fn values() {
+    assert_eq!(parse("true"), Bool(true));
+    assert_eq!(parse(" false "), Bool(false));
+    assert_eq!(parse("\nnull"), Null);
+    assert_eq!(parse("\"\""), text(""));
+    assert_eq!(parse("[]"), Arr(vec![]));
+    assert_eq!(parse("{}"), Data(HashMap::new()));
+    assert_eq!(parse(""), JsonData::None);
+    assert_eq!(parse(" \t\r\n"), JsonData::None);
+    assert_eq!(
+        parse(r#"[{"name":"malina", "age":19},{}, 45.8]"#),
+        Arr(vec![obj([("name", text("malina")), ("age", Num(19.0))]), obj([]), Num(45.8)])
+    );
+    assert_eq!(
+        parse(r#"{"a":{"b":[true,null,{"c":[[]]}]}}"#),
+        obj([(
+            "a",
+            obj([("b", Arr(vec![Bool(true), Null, obj([("c", Arr(vec![Arr(vec![])]))])]))])
+        )])
+    );
+    assert_eq!(parse(r#"{"a":1,"a":2}"#), obj([("a", Num(2.0))]));
+}
+
+#[test]
+fn numbers() {
+    let cases = [
+        ("0", 0.0),
+        ("-0", -0.0),
+        ("300", 300.0),
+        ("-42.6", -42.6),
+        ("1.562e45", 1.562e45),
+        ("0.56e3", 560.0),
+        ("0.56e-2", 0.0056),
+        ("70e12", 70e12),
+        ("1.2E03", 1200.0),
+        ("1E+2", 100.0),
+        ("123456789012345678901234567890", 1.2345678901234568e29),
+        ("1e400", f64::INFINITY),
+        ("5e-324", 5e-324),
+    ];
+    for (json, num) in cases {
+        assert_eq!(parse(json), Num(num), "{json}");
+        assert_eq!(parse(&format!("[{json}]")), Arr(vec![Num(num)]), "{json}");
+        assert_eq!(parse(&format!("{{\"n\":{json}}}")), obj([("n", Num(num))]), "{json}")
+    }
+    for json in ["01", "-", "1.", ".5", "1e", "1e+", "+1", "1.e3", "-01", "--1"] {
+        assert!(matches!(parse(json), JsonData::Err(_)), "{json}");
+        assert!(matches!(parse(&format!("[{json}]")), JsonData::Err(_)), "{json}")
+    }
+    for (json, first) in [("1.5.2", 1.5), ("0x10", 0.0), ("1e2.5", 100.0)] {
+        assert_eq!(parse(json), Num(first), "{json}");
+        assert!(matches!(parse(&format!("[{json}]")), JsonData::Err(_)), "{json}")
+    }
+    assert!(is_number("-0.5e+10"));
+    assert!(!is_number("00"));
+    assert!(!is_number(""));
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(
+        parse(r#""\"\\\/\b\f\n\r\t""#),
+        text("\"\\/\u{8}\u{c}\n\r\t")
+    );
+    assert_eq!(parse(r#""\u0041\u00e9\u4e16\U0042""#), text("Aé世B"));
+    assert_eq!(parse(r#""\u0000""#), text("\0"));
+    assert_eq!(parse(r#""\x\q""#), text("\\x\\q"));
+    assert_eq!(parse(r#"{"k\"ey\n":"v"}"#), obj([("k\"ey\n", text("v"))]));
+    assert_eq!(parse("\"tab\there\""), text("tab\there"));
+    assert_eq!(parse("\"Добрий 👧\""), text("Добрий 👧"));
+    assert_eq!(err(r#""\u12x4""#), "an error: expected a hex digit of \\u escape, found 'x' at 6:1");
+    assert_eq!(err(r#"{"\u12"#), "an error: unterminated string at 2:1");
+}
+
+#[test]
+fn surrogates() {
+    assert_eq!(parse(r#""\ud83d\udc67!""#), text("👧!"));
+    assert_eq!(parse(r#"{"\uD83D\uDE00":1}"#), obj([("😀", Num(1.0))]));
+    assert_eq!(parse(r#""\ud83d""#), text("\u{fffd}"));
+    assert_eq!(parse(r#""\ud83dx""#), text("\u{fffd}x"));
+    assert_eq!(parse(r#""\ud83d\n""#), text("\u{fffd}\n"));
+    assert_eq!(parse(r#""\ud83d\u0041""#), text("\u{fffd}A"));
+    assert_eq!(parse(r#""\ud83d\ud83d\ude00""#), text("\u{fffd}😀"));
+    assert_eq!(parse(r#""\ude00""#), text("\u{fffd}"));
+    assert_eq!(
+        parse_fragment(&mut r#""\ud83d\udc67""#.chars()).0,
+        text("👧")
+    );
+    let options = |lone_surrogates| ParseOptions {
+        lone_surrogates,
+        ..Default::default()
+    };
+    let escape = options(LoneSurrogates::Escape);
+    assert_eq!(parse_with(r#""\ud83dx""#, &escape), text("\\ud83dx"));
+    assert_eq!(parse_with(r#""\uD83D\n""#, &escape), text("\\ud83d\n"));
+    assert_eq!(parse_with(r#""\ud83d\ud83d\ude00""#, &escape), text("\\ud83d😀"));
+    assert_eq!(parse_with(r#"{"\ude00":1}"#, &escape), obj([("\\ude00", Num(1.0))]));
+    // the escaped text survives a round trip, the surrogate doesn't
+    let value = parse_with(r#"["a\ud83db", "\\ud83d"]"#, &escape);
+    assert_eq!(value, Arr(vec![text("a\\ud83db"), text("\\ud83d")]));
+    let json = to_json(&value);
+    assert_eq!(json, r#"["a\\ud83db","\\ud83d"]"#);
+    assert_eq!(parse_with(&json, &escape), value);
+    let error = options(LoneSurrogates::Error);
+    assert_eq!(parse_with(r#""\ud83d\udc67""#, &error), text("👧"));
+    let err = |json| match parse_with(json, &error) {
+        JsonData::Err(err) => err,
+        value => panic!("{value:?}"),
+    };
+    assert_eq!(err(r#""\ud83d""#), "an error: lone surrogate \\ud83d at 2:1");
+    assert_eq!(err(r#"["ab\ud83dx"]"#), "an error: lone surrogate \\ud83d at 5:1");
+    assert_eq!(err(r#""\ud83d\t""#), "an error: lone surrogate \\ud83d at 2:1");
+    assert_eq!(err(r#""\ud83d\u0041""#), "an error: lone surrogate \\ud83d at 2:1");
+    assert_eq!(err("\n \"\\ude00\""), "an error: lone surrogate \\ude00 at 3:2");
+    assert_eq!(
+        parse_tolerant_with(r#"["\ud83d\ud83d\ude00", "\udc00x"]"#, &error),
+        (
+            Arr(vec![text("\u{fffd}😀"), text("\u{fffd}x")]),
+            vec![
+                "an error: lone surrogate \\ud83d at 3:1".to_string(),
+                "an error: lone surrogate \\udc00 at 25:1".to_string()
+            ]
+        )
+    );
+}
+
+#[test]
+fn error_messages() {
+    assert_eq!(err("[1,]"), "an error: expected a value after ',', found ']' at 4:1");
+    assert_eq!(err("{\"a\":1,}"), "an error: expected a key after ',', found '}' at 8:1");
+    assert_eq!(err("{\"a\" 1}"), "an error: expected ':' after key, found '1' at 6:1");
+    assert_eq!(err("[1 2]"), "an error: expected ',' or ']' after array element, found '2' at 4:1");
+    assert_eq!(err("{\"a\":1 \"b\"}"), "an error: expected ',' or '}' after object member, found '\"' at 8:1");
+    assert_eq!(err("[\n  tru,\n]"), "an error: expected true, found ',' at 6:2");
+    assert_eq!(err("[01]"), "an error: invalid number 01 at 2:1");
+    assert_eq!(err("x"), "an error: expected a value, found 'x' at 1:1");
+    assert_eq!(err("{\"a\":\n}"), "an error: expected a value, found '}' at 1:2");
+    assert_eq!(err("[1"), "an error: expected ',' or ']' after array element, found the end of input at 3:1");
+    assert_eq!(err("[1 "), "an error: expected ',' or ']' after array element, found the end of input at 4:1");
+    assert_eq!(err("{\"a\":\"b"), "an error: unterminated string at 6:1");
+    assert_eq!(err("\"\u{e9}\\"), "an error: unterminated string at 1:1");
+    assert_eq!(err("nul"), "an error: expected null, found the end of input at 4:1");
+    assert_eq!(err("-"), "an error: invalid number - at 1:1");
+    assert_eq!(parse("[[1]]\n[2"), parse("[[1]]"), "the rest after the first value is ignored");
+}
+
+#[test]
+fn limits() {
+    let options = ParseOptions {
+        max_depth: 2,
+        max_string_len: 3,
+        max_object_members: 2,
+        max_array_elements: 2,
+        max_number_len: 3,
+        ..Default::default()
+    };
+    let limited = |json: &str| match parse_with(json, &options) {
+        JsonData::Err(err) => err,
+        other => panic!("{json} parsed to {other:?}"),
+    };
+    assert_eq!(limited("[[[]]]"), "an error: nesting depth exceeds 2 at 3:1");
+    assert_eq!(limited("\"abcd\""), "an error: string length exceeds 3 bytes at 5:1");
+    assert_eq!(limited("{\"a\":1,\"b\":2,\"c\":3}"), "an error: object members exceed 2 at 14:1");
+    assert_eq!(limited("[1,2,3]"), "an error: array elements exceed 2 at 6:1");
+    assert_eq!(limited("12345"), "an error: number length exceeds 3 chars at 4:1");
+    assert_eq!(parse_with("{\"a\":[\"abc\",123]}", &options), parse("{\"a\":[\"abc\",123]}"));
+    let short = ParseOptions {
+        max_input_len: 4,
+        ..Default::default()
+    };
+    assert_eq!(parse_with("[1,2]", &short), JsonData::Err("an error: input length exceeds 4 bytes at 5:1".into()));
+    assert_eq!(parse_with("[12]", &short), Arr(vec![Num(12.0)]));
+    let mut parser = JsonParser::with_options(&ParseOptions {
+        max_input_len: 5,
+        ..Default::default()
+    });
+    let mut values = Vec::new();
+    for chunk in ["[1,2,", "3,4,", "5,6]", " 7"] {
+        if let Parsed::Values(chunk) = parser.feed(chunk) {
+            values.extend(chunk)
+        }
+    }
+    values.extend(parser.finish());
+    assert_eq!(values, [JsonData::Err("an error: input length exceeds 5 bytes at 6:1".into())]);
+    assert!(matches!(parse(&"[".repeat(129)), JsonData::Err(_)));
+    let deep = "[".repeat(10_000) + &"]".repeat(10_000);
+    let options = ParseOptions {
+        max_depth: usize::MAX,
+        ..Default::default()
+    };
+    assert!(matches!(parse_fragment_with(&mut deep.chars(), &options).0, Arr(_)));
+}
+
+#[test]
+fn fragments() {
+    let json = r#"{"simple":"json"}
+         {"another":true} ["again","stop"]
+         17 "text"null 3.5"#;
+    let mut chars = json.chars();
+    let mut next = || parse_fragment(&mut chars);
+    assert_eq!(next(), (obj([("simple", text("json"))]), '}'));
+    assert_eq!(next(), (obj([("another", Bool(true))]), '}'));
+    assert_eq!(next(), (Arr(vec![text("again"), text("stop")]), ']'));
+    assert_eq!(next(), (Num(17.0), ' '));
+    assert_eq!(next(), (text("text"), '"'));
+    assert_eq!(next(), (Null, 'l'));
+    assert_eq!(next(), (Num(3.5), '\0'));
+    assert_eq!(next(), (JsonData::None, '\0'));
+    assert_eq!(next(), (JsonData::None, '\0'));
+
+    let mut chars = "[1][2] 3 x".chars();
+    let values: Vec<JsonData> = JsonStr { chars: &mut chars }.collect();
+    assert_eq!(values.len(), 4);
+    assert!(matches!(values[3], JsonData::Err(_)));
+
+    let mut chars = "1]".chars();
+    assert_eq!(parse_fragment(&mut chars).0, Num(1.0));
+    assert_eq!(chars.next(), None, "the char completing a number is consumed");
+    let mut chars = "[1] tail".chars();
+    parse_fragment(&mut chars);
+    assert_eq!(chars.as_str(), " tail");
+}
+
+#[test]
+fn incremental() {
+    let json = r#"{"name":"Malvika\u00e9","age":19,"kids":[{"n":-1.5e2},true,null]} [1,"2"] 7 "#;
+    let whole = [parse(json), parse(r#"[1,"2"]"#), Num(7.0)];
+    for split in 0..json.len() {
+        if !json.is_char_boundary(split) {
+            continue;
+        }
+        let mut parser = JsonParser::new();
+        let mut values = Vec::new();
+        for chunk in [&json[..split], &json[split..]] {
+            if let Parsed::Values(chunk) = parser.feed(chunk) {
+                values.extend(chunk)
+            }
+        }
+        values.extend(parser.finish());
+        assert_eq!(values, whole, "split at {split}");
+        assert!(!parser.is_pending())
+    }
+    let mut parser = JsonParser::new();
+    assert_eq!(parser.feed("[1, 2"), Parsed::NeedMore);
+    assert!(parser.is_pending());
+    assert_eq!(parser.finish(), [JsonData::Err("an error: expected ',' or ']' after array element, found the end of input at 6:1".into())]);
+}
+
+#[test]
+fn events() {
+    let json = "{\"a\": [1, \"x\"],\n \"b\": null}";
+    let mut reader = JsonReader::new(json.chars());
+    let mut events = Vec::new();
+    while let Some((event, pos)) = reader.next_event() {
+        events.push((format!("{event:?}"), pos.line, pos.column, pos.offset))
+    }
+    let expected = [
+        ("StartObject", 1, 1, 0),
+        ("Key(\"a\")", 1, 2, 1),
+        ("StartArray", 1, 7, 6),
+        ("Number(1.0)", 1, 8, 7),
+        ("String(\"x\")", 1, 11, 10),
+        ("EndArray", 1, 14, 13),
+        ("Key(\"b\")", 2, 2, 17),
+        ("Null", 2, 7, 22),
+        ("EndObject", 2, 11, 26),
+    ];
+    assert_eq!(events.len(), expected.len());
+    let mut reader = JsonReader::new("12[".chars());
+    assert_eq!(reader.next_event(), Some((Event::Number(12.0), Position::default())));
+    for ((event, line, column, offset), expected) in events.iter().zip(expected) {
+        assert_eq!((event.as_str(), *line, *column, *offset), expected)
+    }
+    // an error is reported where its message points
+    for (json, at) in [("[1,", "4:1"), ("[1,\n}", "1:2"), ("[\"ab", "2:1"), ("01", "1:1")] {
+        let mut reader = JsonReader::new(json.chars());
+        let (err, pos) = std::iter::from_fn(|| reader.next_event().map(|(event, pos)| (format!("{event:?}"), pos)))
+            .last()
+            .unwrap();
+        assert!(err.contains(&format!(" at {at}")), "{json}: {err}");
+        assert_eq!(format!("{}:{}", pos.column, pos.line), at, "{json}: {err}")
+    }
+}
+
+#[test]
+fn skipping() {
+    let mut reader = JsonReader::new("[[1], 2]".chars());
+    assert_eq!(reader.next_event().unwrap().0, Event::StartArray);
+    reader.skip_value();
+    assert_eq!(reader.next_event().unwrap().0, Event::Number(2.0));
+    reader.skip_value();
+    assert_eq!(reader.next_event().unwrap().0, Event::EndArray, "nothing to skip");
+    let mut reader = JsonReader::new("[{\"a\":[1,}]".chars());
+    reader.next_event();
+    reader.skip_value();
+    assert!(matches!(reader.next_event().unwrap().0, Event::Err(_)));
+    assert_eq!(reader.next_event(), None)
+}
+
+#[test]
+fn extraction() {
+    assert_eq!(extract(r#"{"a\"bc":1,"x":2}"#, &["a\"bc", "x"]), [Some(Num(1.0)), Some(Num(2.0))]);
+    assert_eq!(
+        extract(r#"{"a\nb":{"c\u0041":[1,"\t"]}}"#, &["a\nb/cA/1", "a\nb/cA/0", "a\nb/cB"]),
+        [Some(text("\t")), Some(Num(1.0)), None]
+    );
+    assert_eq!(
+        extract(r#"[[1,2],{"k":[3]}]"#, &["0/1", "1/k/0", "1/k", "2", "0/x", "1/k/0/0"]),
+        [Some(Num(2.0)), Some(Num(3.0)), Some(Arr(vec![Num(3.0)])), None, None, None]
+    );
+    // a scalar has nothing inside
+    assert_eq!(extract("7", &["", "a", "0"]), [None, None, None]);
+    assert_eq!(extract(r#""x\ty""#, &["x"]), [None]);
+    assert_eq!(extract("1[2]", &["0"]), [None]);
+    assert_eq!(extract("{\"a\":1}{\"a\":2}", &["a", "b"]), [Some(Num(1.0)), None]);
+    assert_eq!(extract(r#"{"a":1,"b":[2,}"#, &["b/0", "a"]), [Some(Num(2.0)), Some(Num(1.0))]);
+
+    // repeated keys resolve the same way as in parse
+    let lookup = |value: &JsonData, path: &str| {
+        path.split('/').try_fold(value.clone(), |value, comp| match value {
+            Data(mut obj) => obj.remove(comp),
+            Arr(mut arr) => comp.parse().ok().filter(|&inx| inx < arr.len()).map(|inx| arr.swap_remove(inx)),
+            _ => None,
+        })
+    };
+    let paths = ["a", "a/b", "a/1", "c", "0/x/y"];
+    for json in [
+        r#"{"a":1,"a":2}"#,
+        r#"{"a":{"b":1},"a":2,"c":3}"#,
+        r#"{"a":{"b":1},"c":0,"a":{"b":3},"c":[4]}"#,
+        r#"{"a":[1,2],"a":[5],"a":[6,{"b":7}]}"#,
+        r#"[{"x":1,"x":{"y":2}},{"x":3}]"#,
+        r#"{"a":{"b":1,"b":2},"c":{"d":1,"d":2}}"#,
+    ] {
+        let value = parse(json);
+        let expected: Vec<_> = paths.iter().map(|path| lookup(&value, path)).collect();
+        assert_eq!(extract(json, &paths), expected, "{json}");
+    }
+    let options = ParseOptions {
+        duplicate_keys: DuplicateKeys::Error,
+        ..Default::default()
+    };
+    let json = r#"{"a":1,"b":{"c":2,"c":3}}"#;
+    let err = Some(JsonData::Err("an error: duplicate key \"c\" at 19:1, first at 13:1".into()));
+    assert_eq!(parse_with(json, &options), err.clone().unwrap());
+    assert_eq!(extract_with(json, &["a", "x"], &options), [err.clone(), err]);
+    assert_eq!(extract_with(r#"{"a":1,"b":[{"a":2}]}"#, &["a"], &options), [Some(Num(1.0))]);
+}
+
+#[test]
+fn json_lines() {
+    let lines = "{\"a\":1}\r\n\n[2] \r\n  \n{\"b\":2} {\"c\":3}\n3 4\n\"x\"\n[1,\n7";
+    let expected = vec![
+        (1, obj([("a", Num(1.0))])),
+        (3, Arr(vec![Num(2.0)])),
+        (5, JsonData::Err("an error: text after the value at 9:1".to_string())),
+        (6, JsonData::Err("an error: text after the value at 3:1".to_string())),
+        (7, text("x")),
+        (8, JsonData::Err("an error: expected a value after ',', found the end of input at 4:1".to_string())),
+        (9, Num(7.0)),
+    ];
+    for threads in [1, 2, 3, 7, 100] {
+        assert_eq!(parse_lines(lines, threads), expected, "{threads} threads")
+    }
+    let many: String = (0..1000).map(|n| if n % 10 == 3 { "\n".to_string() } else { format!("[{n}]\n") }).collect();
+    let lines = parse_lines(&many, 8);
+    assert_eq!(lines, parse_lines(&many, 1));
+    assert_eq!(lines.len(), 900);
+    assert!(lines.iter().all(|(line, value)| *value == Arr(vec![Num((line - 1) as f64)])));
+    let options = ParseOptions {
+        jsonc: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_lines_with("1 // one\n2 /* two", 2, &options),
+        [(1, Num(1.0)), (2, JsonData::Err("an error: unterminated comment at 9:1".to_string()))]
+    );
+}
+
+#[test]
+fn first_value_only() {
+    for json in ["2] 3", "1[2]", "[1] x", "7"] {
+        let value = parse(json);
+        assert_eq!(JsonData::from(&JsonDoc::parse(json).unwrap()), value, "{json}");
+        assert_eq!(
+            LazyJson::new(json).unwrap().pointer(""),
+            Some(value),
+            "{json}"
+        )
+    }
+}
+
+#[test]
+fn limits_without_copying() {
+    let options = ParseOptions {
+        max_string_len: 3,
+        ..Default::default()
+    };
+    assert!(LazyJson::with_options("[\"abc\"]", &options).is_ok());
+    assert!(LazyJson::with_options("[\"abcd\"]", &options).is_err());
+    let mut reader = JsonReader::with_options("[\"abcd\"]".chars(), &options);
+    reader.next_event();
+    reader.skip_value();
+    assert!(matches!(reader.next_event().unwrap().0, Event::Err(_)))
+}
+
+#[test]
+fn json5() {
+    let options = ParseOptions {
+        json5: true,
+        ..Default::default()
+    };
+    let json5 = |json: &str| parse_with(json, &options);
+    let json = r#"// settings
+{
+  unquoted: 'and you can quote me on that',
+  singleQuotes: 'I can use "double quotes" here',
+  lineBreaks: "Look, Mom! \
+No \\n's!",
+  hexadecimal: 0xdecaf,
+  leadingDecimalPoint: .8675309, andTrailing: 8675309.,
+  positiveSign: +1,
+  /* trailing commas */
+  trailingComma: 'in objects', andIn: ['arrays',],
+  "backwardsCompatible": "with JSON",
+}"#;
+    assert_eq!(
+        json5(json),
+        obj([
+            ("unquoted", text("and you can quote me on that")),
+            ("singleQuotes", text("I can use \"double quotes\" here")),
+            ("lineBreaks", text("Look, Mom! No \\n's!")),
+            ("hexadecimal", Num(912559.0)),
+            ("leadingDecimalPoint", Num(0.8675309)),
+            ("andTrailing", Num(8675309.0)),
+            ("positiveSign", Num(1.0)),
+            ("trailingComma", text("in objects")),
+            ("andIn", Arr(vec![text("arrays")])),
+            ("backwardsCompatible", text("with JSON")),
+        ])
+    );
+    assert!(matches!(parse(json), JsonData::Err(_)));
+    assert_eq!(
+        json5("[Infinity, -Infinity, +0x1F, -0X10, 1e3, -.5e-1, /**/ 2 // end\n,]"),
+        Arr(vec![
+            Num(f64::INFINITY),
+            Num(f64::NEG_INFINITY),
+            Num(31.0),
+            Num(-16.0),
+            Num(1000.0),
+            Num(-0.05),
+            Num(2.0)
+        ])
+    );
+    assert!(matches!(json5("NaN"), Num(num) if num.is_nan()));
+    assert_eq!(json5(r"'\x41\v\0\'\q\u00e9'"), text("A\u{b}\0'qé"));
+    assert_eq!(json5("'a\\\r\nb\\\rc\\\u{2028}d'"), text("abcd"));
+    assert_eq!(json5("{$a_1: 1, _b: 2, ünï: 3}"), obj([("$a_1", Num(1.0)), ("_b", Num(2.0)), ("ünï", Num(3.0))]));
+    assert_eq!(json5("\u{feff}\u{a0}[1]\u{2028}"), Arr(vec![Num(1.0)]));
+    assert_eq!(json5("5 // five"), Num(5.0));
+    assert_eq!(parse_fragment_with(&mut "{a:1}//\n[2]".chars(), &options).0, obj([("a", Num(1.0))]));
+    let JsonRef::Data(data) = parse_ref_with("{key: 'v'}", &options) else {
+        panic!()
+    };
+    assert!(matches!(data.keys().next(), Some(Cow::Borrowed("key"))));
+    let lazy = LazyJson::with_options("{a: 1, 'b': {c: 'x'}}", &options).unwrap();
+    assert_eq!(lazy.get(&"b/c"), Some(text("x")));
+    assert_eq!(lazy.raw(&"b"), Some("{c: 'x'}"));
+
+    let err = |json: &str| match json5(json) {
+        JsonData::Err(err) => err,
+        other => panic!("{json} parsed to {other:?}"),
+    };
+    assert_eq!(err("[1, 01]"), "an error: invalid number 01 at 5:1");
+    assert_eq!(err("[0x]"), "an error: invalid number 0x at 2:1");
+    assert_eq!(err("[.]"), "an error: invalid number . at 2:1");
+    assert_eq!(err("[Inf]"), "an error: invalid number Inf at 2:1");
+    assert_eq!(err("[1.2.3]"), "an error: invalid number 1.2.3 at 2:1");
+    assert_eq!(err("'a\\1'"), "an error: invalid escape \\1 at 4:1");
+    assert_eq!(err("[1 /x]"), "an error: invalid comment, / followed by 'x' at 5:1");
+    assert_eq!(err("[1 /* open"), "an error: unterminated comment at 11:1");
+    assert_eq!(err("[1,,]"), "an error: expected a value after ',', found ',' at 4:1");
+    assert_eq!(err("{,}"), "an error: expected a key or '}', found ',' at 2:1");
+    assert_eq!(err("{a b}"), "an error: expected ':' after key, found 'b' at 4:1");
+}
+
+#[test]
+fn jsonc() {
+    let options = ParseOptions {
+        jsonc: true,
+        ..Default::default()
+    };
+    let jsonc = |json: &str| parse_with(json, &options);
+    let json = r#"// Place your settings in this file
+{
+    "editor.fontSize": 14, // points
+    /* "editor.tabSize": 8, */
+    "files.exclude": {
+        "**/.git": true,
+    },
+    "words": ["a", "b",],
+}
+"#;
+    assert_eq!(
+        jsonc(json),
+        obj([
+            ("editor.fontSize", Num(14.0)),
+            ("files.exclude", obj([("**/.git", Bool(true))])),
+            ("words", Arr(vec![text("a"), text("b")])),
+        ])
+    );
+    assert!(matches!(parse(json), JsonData::Err(_)));
+    assert_eq!(jsonc("[1 /* a ** b */, 2]//"), Arr(vec![Num(1.0), Num(2.0)]));
+    assert_eq!(jsonc(r#"{"a//b": "/*c*/"}"#), obj([("a//b", text("/*c*/"))]));
+    let lazy = LazyJson::with_options("{\"a\": [1, // one\n 2,],}", &options).unwrap();
+    assert_eq!(lazy.get(&"a/1"), Some(Num(2.0)));
+
+    let err = |json: &str| match jsonc(json) {
+        JsonData::Err(err) => err,
+        other => panic!("{json} parsed to {other:?}"),
+    };
+    assert_eq!(err("{a: 1}"), "an error: expected a key or '}', found 'a' at 2:1");
+    assert_eq!(err("['a']"), "an error: expected a value or ']', found '\\'' at 2:1");
+    assert_eq!(err("[0x1]"), "an error: expected ',' or ']' after array element, found 'x' at 3:1");
+    assert_eq!(err("[1 /* open"), "an error: unterminated comment at 11:1");
+    assert_eq!(err("[1,,]"), "an error: expected a value after ',', found ',' at 4:1");
+}
+
+#[test]
+fn editing() {
+    let options = ParseOptions {
+        jsonc: true,
+        ..Default::default()
+    };
+    let settings = r#"{
+    // editor
+    "editor.fontSize": 14, /* points */
+    "files.exclude": {
+        "**/.git": true // hidden
+    },
+    "words": [ "a", "b" ]
+}
+"#;
+    let mut doc = JsonEdit::with_options(settings, &options).unwrap();
+    assert_eq!(doc.get(&"files.exclude/**~1.git"), None);
+    assert_eq!(doc.raw(&"words"), Some(r#"[ "a", "b" ]"#));
+    doc.set(&"editor.fontSize", &Num(16.0)).unwrap();
+    doc.set(&"files.exclude/**/.git", &Bool(false)).unwrap_err();
+    doc.set(&"files.exclude/node_modules", &Bool(true)).unwrap();
+    doc.set(&"words/2", &text("c")).unwrap();
+    doc.set(&"theme", &obj([("dark", Bool(true))])).unwrap();
+    assert_eq!(
+        doc.as_str(),
+        r#"{
+    // editor
+    "editor.fontSize": 16, /* points */
+    "files.exclude": {
+        "**/.git": true, // hidden
+        "node_modules": true
+    },
+    "words": [ "a", "b", "c" ],
+    "theme": {"dark":true}
+}
+"#
+    );
+    assert_eq!(doc.remove(&"words/0"), Some(text("a")));
+    assert_eq!(doc.remove(&"editor.fontSize"), Some(Num(16.0)));
+    assert_eq!(doc.remove(&"theme"), Some(obj([("dark", Bool(true))])));
+    assert_eq!(doc.remove(&"theme"), None);
+    assert_eq!(
+        doc.to_string(),
+        r#"{
+    // editor
+    /* points */
+    "files.exclude": {
+        "**/.git": true, // hidden
+        "node_modules": true
+    },
+    "words": [ "b", "c" ]
+}
+"#
+    );
+    assert_eq!(parse_with(doc.as_str(), &options), parse(&to_json(&parse_with(doc.as_str(), &options))));
+
+    let edit = |json: &str, path: &str, value: Option<JsonData>| {
+        let mut doc = JsonEdit::with_options(json, &options).unwrap();
+        match value {
+            Some(value) => doc.set(&path, &value).unwrap(),
+            None => drop(doc.remove(&path)),
+        }
+        doc.to_string()
+    };
+    assert_eq!(edit("{}", "a", Some(Null)), r#"{"a": null}"#);
+    assert_eq!(edit("{\"a\" :1}", "b", Some(Null)), r#"{"a" :1, "b" :null}"#);
+    assert_eq!(edit("[1, 2,]", "2", Some(Num(3.0))), "[1, 2, 3,]");
+    assert_eq!(edit("{\r\n  \"a\": {\r\n  }\r\n}", "a/b", Some(Null)), "{\r\n  \"a\": {\r\n    \"b\": null\r\n  }\r\n}");
+    assert_eq!(edit("[\n\t1,\n]", "1", Some(Num(2.0))), "[\n\t1,\n\t2,\n]");
+    assert_eq!(edit("[1, /* 2 */ 3]", "1", None), "[1 /* 2 */]");
+    assert_eq!(edit("{ \"a\": 1 }", "a", None), "{ }");
+    assert_eq!(edit("[\n  1,\n  2\n]", "0", None), "[\n  2\n]");
+    assert_eq!(edit("{\"a\":1, // c\n \"b\":2}", "b", None), "{\"a\":1 // c\n}");
+    assert_eq!(edit("{ // c\r\n  \"a\":1\r\n}", "a", None), "{ // c\r\n}");
+    assert_eq!(edit("[\n  1,\n  2\n]", "1", None), "[\n  1\n]");
+    assert_eq!(edit("[\n  // c\n  1 // one\n]", "0", None), "[\n  // c\n]");
+    assert_eq!(edit("[\n  0,\n  1 // one\n]", "1", None), "[\n  0\n]");
+    assert_eq!(edit("[\n  1, // one\n  2\n]", "0", None), "[\n  2\n]");
+    assert_eq!(edit("{\r\n  \"a\": 1, // a\r\n  \"b\": 2 // b\r\n}", "a", None), "{\r\n  \"b\": 2 // b\r\n}");
+    assert_eq!(edit("[1, // one\n 2]", "0", None), "[\n 2]");
+    assert_eq!(edit("[0, 1 // one\n]", "1", None), "[0\n]");
+
+    let mut doc = JsonEdit::with_options("{\"**/.git\": true, \"a~\": {}}", &options).unwrap();
+    assert_eq!(doc.pointer("/**~1.git"), Some(Bool(true)));
+    doc.set_pointer("/**~1.git", &Bool(false)).unwrap();
+    doc.set_pointer("/a~0/b~1c", &Num(1.0)).unwrap();
+    assert_eq!(doc.remove_pointer("/a~0/b~1c"), Some(Num(1.0)));
+    assert_eq!(doc.set_pointer("a", &Null), Err("an error: invalid pointer a".to_string()));
+    assert_eq!(doc.as_str(), "{\"**/.git\": false, \"a~\": {}}");
+
+    let mut doc = JsonEdit::new("{\"a\": [1]}").unwrap();
+    assert_eq!(doc.set(&"a/5", &Null), Err("an error: index 5 is out of 1 elements of a/5".to_string()));
+    assert_eq!(doc.set(&"b/c", &Null), Err("an error: no object or array holds b/c".to_string()));
+    assert_eq!(doc.set(&"a/0/x", &Null), Err("an error: no object or array holds a/0/x".to_string()));
+    assert_eq!(doc.set(&"a/0", &JsonData::None), Err("an error: a value to set at a/0 holds None or Err".to_string()));
+    let nested = Arr(vec![Num(1.0), obj([("e", JsonData::Err("x".into()))])]);
+    assert_eq!(doc.set(&"b", &nested), Err("an error: a value to set at b holds None or Err".to_string()));
+    assert_eq!(doc.set_pointer("/b", &JsonData::Err("x".into())), Err("an error: a value to set at /b holds None or Err".to_string()));
+    assert_eq!(doc.as_str(), "{\"a\": [1]}");
+}
+
+#[test]
+fn duplicate_keys() {
+    let json = r#"{"a": 1, "b": {"c": true}, "a": [2], "\u0061": "3"}"#;
+    let options = |duplicate_keys| ParseOptions {
+        duplicate_keys,
+        ..Default::default()
+    };
+    let last = options(DuplicateKeys::LastWins);
+    let first = options(DuplicateKeys::FirstWins);
+    let collect = options(DuplicateKeys::Collect);
+    let error = options(DuplicateKeys::Error);
+    let b = obj([("c", Bool(true))]);
+    let expected = [
+        (&last, obj([("a", text("3")), ("b", b.clone())])),
+        (&first, obj([("a", Num(1.0)), ("b", b.clone())])),
+        (
+            &collect,
+            obj([("a", Arr(vec![Num(1.0), Arr(vec![Num(2.0)]), text("3")])), ("b", b.clone())]),
+        ),
+    ];
+    for (options, value) in expected {
+        assert_eq!(parse_with(json, options), value);
+        assert_eq!(parse_ref_with(json, options).into_owned(), value);
+        assert_eq!(parse_fragment_with(&mut json.chars(), options).0, value);
+        assert_eq!(JsonData::from(&JsonDoc::parse_with(json, options).unwrap()), value);
+        assert_eq!(JsonData::from(&JsonDoc::parse_interned(json, options).unwrap()), value);
+        let mut parser = JsonParser::with_options(options);
+        assert_eq!(parser.feed(json), Parsed::Values(vec![value.clone()]));
+        let first_or_last = match options.duplicate_keys {
+            DuplicateKeys::LastWins => text("3"),
+            _ => Num(1.0),
+        };
+        let doc = JsonDoc::parse_with(json, options).unwrap();
+        assert_eq!(doc.path(&"a").map(|a| a.to_data()), Some(first_or_last.clone()));
+        let lazy = LazyJson::with_options(json, options).unwrap();
+        assert_eq!(lazy.get(&"a"), Some(first_or_last.clone()));
+        assert_eq!(extract_with(json, &["a", "b/c", "a/0"], options), [Some(first_or_last.clone()), Some(Bool(true)), None]);
+        let mut doc = JsonEdit::with_options(json, options).unwrap();
+        doc.set(&"a", &Null).unwrap();
+        assert_eq!(doc.get(&"a"), Some(Null));
+    }
+    assert_eq!(parse_with(r#"{"a": {"a": 1}}"#, &collect), obj([("a", obj([("a", Num(1.0))]))]));
+    assert_eq!(parse_with(r#"[{"a": 1}, {"a": 2}]"#, &error), Arr(vec![obj([("a", Num(1.0))]), obj([("a", Num(2.0))])]));
+
+    let err = "an error: duplicate key \"a\" at 28:1, first at 2:1";
+    assert_eq!(parse_with(json, &error), JsonData::Err(err.to_string()));
+    assert_eq!(parse_ref_with(json, &error).into_owned(), JsonData::Err(err.to_string()));
+    assert_eq!(JsonDoc::parse_with(json, &error), Err(err.to_string()));
+    assert_eq!(LazyJson::with_options(json, &error).unwrap_err(), err);
+    assert_eq!(JsonEdit::with_options(json, &error).unwrap_err(), err);
+    let mut parser = JsonParser::with_options(&error);
+    assert_eq!(parser.feed(&format!("{json} [1]")), Parsed::Values(vec![JsonData::Err(err.to_string())]));
+    assert_eq!(parser.finish(), []);
+    let json5 = ParseOptions { json5: true, ..error.clone() };
+    assert_eq!(
+        LazyJson::with_options("{'\"': 1, s\"", &json5).unwrap_err(),
+        "an error: expected ':' after key, found '\"' at 11:1"
+    );
+    assert_eq!(
+        parse_with("{\"x\": 0,\n \"\\u0078\": 1}", &error),
+        JsonData::Err("an error: duplicate key \"x\" at 2:2, first at 2:1".to_string())
+    );
+}
+
+#[test]
+fn borrowed() {
+    let json = r#"{"plain":"text","esc":"a\nb","arr":["x",1]}"#;
+    let JsonRef::Data(data) = parse_ref(json) else {
+        panic!()
+    };
+    assert!(matches!(data["plain"], JsonRef::Text(Cow::Borrowed("text"))));
+    assert!(matches!(&data["esc"], JsonRef::Text(Cow::Owned(text)) if text == "a\nb"));
+    assert_eq!(parse_ref(json).into_owned(), parse(json))
+}
+
+#[test]
+fn serializer() {
+    assert_eq!(to_json(&parse("[]")), "[]");
+    assert_eq!(to_json(&parse("{}")), "{}");
+    assert_eq!(to_json(&parse(r#"{"a":[1,true,null,"x"]}"#)), r#"{"a":[1,true,null,"x"]}"#);
+    assert_eq!(to_json(&text("\"\\/\u{8}\u{c}\n\r\t\u{1}\u{7f}é")), "\"\\\"\\\\/\\b\\f\\n\\r\\t\\u0001\u{7f}é\"");
+    assert_eq!(to_json(&Num(-0.0)), "-0");
+    assert_eq!(to_json(&Num(0.1)), "0.1");
+    assert_eq!(to_json(&Num(1e16)), "1e16");
+    assert_eq!(to_json(&Num(123456789.0)), "123456789");
+    assert_eq!(to_json(&Num(-2.5e-8)), "-2.5e-8");
+    assert_eq!(to_json(&Num(f64::NAN)), "null");
+    assert_eq!(to_json(&Num(f64::NEG_INFINITY)), "null");
+    assert_eq!(to_json(&JsonData::None), "null");
+    assert_eq!(to_json(&JsonData::Err("an error".into())), "null");
+}
+
+#[test]
+fn non_finite() {
+    let options = ParseOptions {
+        non_finite: true,
+        ..Default::default()
+    };
+    let parsed = parse_with("[NaN, Infinity, -Infinity, 1e3, -0.5]", &options);
+    let Arr(arr) = &parsed else {
+        panic!("{parsed:?}")
+    };
+    assert!(matches!(arr[0], Num(num) if num.is_nan()));
+    assert_eq!(arr[1..], [Num(f64::INFINITY), Num(f64::NEG_INFINITY), Num(1000.0), Num(-0.5)]);
+    assert_eq!(parse_with("-Infinity", &options), Num(f64::NEG_INFINITY));
+    assert_eq!(parse_with(r#"{"max":Infinity}"#, &options), obj([("max", Num(f64::INFINITY))]));
+    assert_eq!(LazyJson::with_options("[1,Infinity]", &options).unwrap().get(&"1"), Some(Num(f64::INFINITY)));
+    assert_eq!(err("[NaN]"), "an error: expected a value or ']', found 'N' at 2:1");
+    let err = |json: &str| match parse_with(json, &options) {
+        JsonData::Err(err) => err,
+        other => panic!("{json} parsed to {other:?}"),
+    };
+    assert_eq!(err("[Inf]"), "an error: invalid number Inf at 2:1");
+    assert_eq!(err("[+Infinity]"), "an error: expected a value or ']', found '+' at 2:1");
+    assert_eq!(err("[Infinityx]"), "an error: invalid number Infinityx at 2:1");
+    assert_eq!(err("[nan]"), "an error: expected null, found 'a' at 3:1");
+    assert_eq!(err("[1x]"), "an error: invalid number 1x at 2:1");
+    assert_eq!(err("[01]"), "an error: invalid number 01 at 2:1");
+
+    let write = |non_finite| WriteOptions { non_finite };
+    let json = obj([("a", Arr(vec![Num(f64::NAN), Num(f64::INFINITY), Num(f64::NEG_INFINITY), Num(2.0)]))]);
+    assert_eq!(to_json_with(&json, &write(NonFinite::Null)), r#"{"a":[null,null,null,2]}"#);
+    assert_eq!(to_json_with(&json, &write(NonFinite::Literal)), r#"{"a":[NaN,Infinity,-Infinity,2]}"#);
+    assert_eq!(to_json_with(&json, &write(NonFinite::Text)), r#"{"a":["NaN","Infinity","-Infinity",2]}"#);
+    let again = parse_with(&to_json_with(&json, &write(NonFinite::Literal)), &options);
+    assert_eq!(to_json_with(&again, &write(NonFinite::Text)), to_json_with(&json, &write(NonFinite::Text)));
+}
+
+#[test]
+fn encodings() {
+    let json = "{\"a\": [\"é😀\", 1]}";
+    let expected = obj([("a", Arr(vec![text("é😀"), Num(1.0)]))]);
+    let utf16 = |bom: &[u8], le: bool| {
+        let units = json.encode_utf16();
+        let mut bytes = bom.to_vec();
+        bytes.extend(units.flat_map(|unit| if le { unit.to_le_bytes() } else { unit.to_be_bytes() }));
+        bytes
+    };
+    let utf32 = |bom: &[u8], le: bool| {
+        let mut bytes = bom.to_vec();
+        bytes.extend(json.chars().flat_map(|c| if le { (c as u32).to_le_bytes() } else { (c as u32).to_be_bytes() }));
+        bytes
+    };
+    let inputs = [
+        json.as_bytes().to_vec(),
+        [b"\xEF\xBB\xBF", json.as_bytes()].concat(),
+        utf16(b"", true),
+        utf16(b"\xFF\xFE", true),
+        utf16(b"", false),
+        utf16(b"\xFE\xFF", false),
+        utf32(b"", true),
+        utf32(b"\xFF\xFE\0\0", true),
+        utf32(b"", false),
+        utf32(b"\0\0\xFE\xFF", false),
+    ];
+    for bytes in inputs {
+        assert_eq!(parse_bytes(&bytes), expected, "{bytes:?}");
+        assert_eq!(decode(&bytes).as_deref(), Ok(json));
+    }
+    assert_eq!(parse_bytes(b"7"), Num(7.0));
+    assert_eq!(parse_bytes(b"7\0"), Num(7.0));
+    assert_eq!(parse_bytes(b"\x007"), Num(7.0));
+    assert_eq!(parse_bytes(b""), JsonData::None);
+    assert!(matches!(decode(b"[1]"), Ok(Cow::Borrowed("[1]"))));
+
+    let err = |bytes: &[u8]| decode(bytes).unwrap_err();
+    assert_eq!(err(b"[\"\xE9\"]"), "an error: invalid UTF-8 at byte 2");
+    assert_eq!(err(b"\xEF\xBB\xBF[\xFF]"), "an error: invalid UTF-8 at byte 4");
+    assert_eq!(err(b"[\0\"\0\x3D\xD8\"\0]\0"), "an error: invalid UTF-16 at byte 4");
+    assert_eq!(err(b"[\0\"\0\x3D\xD8"), "an error: invalid UTF-16 at byte 4");
+    assert_eq!(err(b"[\0\"\0\x00\xDC"), "an error: invalid UTF-16 at byte 4");
+    assert_eq!(err(b"[\0]"), "an error: invalid UTF-16 at byte 2");
+    assert_eq!(err(b"[\0\0\0\0\xD8\0\0]\0\0\0"), "an error: invalid UTF-32 at byte 4");
+    assert_eq!(parse_bytes(b"[\xFF]"), JsonData::Err("an error: invalid UTF-8 at byte 1".to_string()));
+
+    assert_eq!(parse("\u{feff}[1]"), Arr(vec![Num(1.0)]));
+    assert_eq!(LazyJson::new("\u{feff}{\"a\":1}").unwrap().raw(&"a"), Some("1"));
+    assert!(matches!(parse("[\u{feff}1]"), JsonData::Err(_)));
+}
+
+#[test]
+fn diagnostics() {
+    let diagnostic = |json: &str| try_parse(json).unwrap_err();
+    let err = diagnostic("{\"a\": {\"b/c\": [1, {\"x\": tru}]}}");
+    assert_eq!(err.message, "expected true, found '}'");
+    assert_eq!(err.position, Position { offset: 27, line: 1, column: 28 });
+    assert_eq!(err.pointer, "/a/b~1c/1/x");
+    assert_eq!(diagnostic("{\"a\": [1, \"b").pointer, "/a/1");
+    assert_eq!(diagnostic("{\"a\": [1, 2 3]}").pointer, "/a");
+    assert_eq!(diagnostic("{\"a\":}").pointer, "/a");
+    assert_eq!(diagnostic("{\"a\" 1}").pointer, "");
+    assert_eq!(
+        diagnostic("[1,\n\t[2, 3 4]]").to_string(),
+        "error: expected ',' or ']' after array element, found '4'\n --> line 2, column 8, in /1\n  |\n2 | \t[2, 3 4]]\n  | \t      ^\n"
+    );
+    assert_eq!(
+        diagnostic("[1,\n 2").to_string(),
+        "error: expected ',' or ']' after array element, found the end of input\n --> line 2, column 3, in /1\n  |\n2 |  2\n  |   ^\n"
+    );
+    let long = format!("[{}x]", "1,".repeat(100));
+    let lines: Vec<String> = diagnostic(&long).to_string().lines().map(String::from).collect();
+    assert_eq!(lines[3], format!("1 | ...{}x]", &"1,".repeat(30)));
+    assert_eq!(lines[4], format!("  | {}^", " ".repeat(63)));
+
+    let error = ParseOptions {
+        duplicate_keys: DuplicateKeys::Error,
+        ..Default::default()
+    };
+    let err = try_parse_with("[{\"a\": 1,\n \"a\": 2}]", &error).unwrap_err();
+    assert_eq!((err.message.as_str(), err.pointer.as_str()), ("duplicate key \"a\", first at 3:1", "/0"));
+    assert_eq!(err.position.line, 2);
+    assert_eq!(try_parse("[1, {\"a\": null}]"), Ok(parse("[1, {\"a\": null}]")));
+    assert_eq!(try_parse(" "), Ok(JsonData::None));
+}
+
+#[test]
+fn tolerant() {
+    let (json, errors) = parse_tolerant("{\"a\" 1, \"b\":[1 2,,3], \"c\":\"x\n\"d\":tru, \"e\":-}");
+    assert_eq!(
+        json,
+        obj([
+            ("a", Num(1.0)),
+            ("b", Arr(vec![Num(1.0), Num(2.0), JsonData::None, Num(3.0)])),
+            ("c", Text("x".to_string())),
+            ("d", JsonData::None),
+            ("e", JsonData::None),
+        ])
+    );
+    assert_eq!(
+        errors,
+        [
+            "an error: expected ':' after key, found '1' at 6:1",
+            "an error: expected ',' or ']' after array element, found '2' at 16:1",
+            "an error: expected a value after ',', found ',' at 18:1",
+            "an error: unterminated string at 27:1",
+            "an error: expected ',' or '}' after object member, found '\"' at 1:2",
+            "an error: expected true, found ',' at 8:2",
+            "an error: invalid number - at 14:2",
+        ]
+    );
+    let tolerant = |json| parse_tolerant(json);
+    assert_eq!(tolerant("[1, 2]"), (Arr(vec![Num(1.0), Num(2.0)]), vec![]));
+    assert_eq!(tolerant(""), (JsonData::None, vec![]));
+    assert_eq!(
+        tolerant("[\"ab\n{\"k"),
+        (
+            Arr(vec![Text("ab".to_string()), obj([("k", JsonData::None)])]),
+            vec![
+                "an error: unterminated string at 2:1".to_string(),
+                "an error: expected ',' or ']' after array element, found '{' at 1:2".to_string(),
+                "an error: unterminated string at 2:2".to_string()
+            ]
+        )
+    );
+    assert_eq!(tolerant("]{}x"), (obj([]), vec![
+        "an error: expected a value, found ']' at 1:1".to_string(),
+        "an error: text after the value at 4:1".to_string()
+    ]));
+    assert_eq!(tolerant("12 3").1, ["an error: text after the value at 4:1"]);
+    assert_eq!(tolerant("[\"\\u12x4\"]").0, Arr(vec![Text("x4".to_string())]));
+
+    let options = ParseOptions {
+        json5: true,
+        duplicate_keys: DuplicateKeys::Error,
+        ..Default::default()
+    };
+    let (json, errors) = parse_tolerant_with("{a 1, a: 2, /b: [3 /* open", &options);
+    assert_eq!(json, obj([("a", Num(2.0)), ("b", Arr(vec![Num(3.0)]))]));
+    assert_eq!(
+        errors,
+        [
+            "an error: expected ':' after key, found '1' at 4:1",
+            "an error: duplicate key \"a\" at 7:1, first at 2:1",
+            "an error: invalid comment, / followed by 'b' at 14:1",
+            "an error: unterminated comment at 27:1",
+        ]
+    );
+    let options = ParseOptions {
+        max_depth: 2,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_tolerant_with("[1, [[2]], 3]", &options),
+        (Arr(vec![Num(1.0), Arr(vec![])]), vec!["an error: nesting depth exceeds 2 at 6:1".to_string()])
+    );
+    // every string is cut at its line end in one pass over the text
+    let (json, errors) = tolerant(&format!("[{}", "\"abc\n,".repeat(5000)));
+    assert_eq!(json, Arr(vec![text("abc"); 5000]));
+    assert_eq!(errors.len(), 5001);
+    assert_eq!(errors[4999], "an error: unterminated string at 2:5000");
+}
+
+#[test]
+fn partial() {
+    let pointers = |pointers: &[&str]| pointers.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    assert_eq!(parse_partial(""), (JsonData::None, pointers(&[""])));
+    assert_eq!(parse_partial("\"ab\\u00e9"), (text("abé"), pointers(&[""])));
+    assert_eq!(parse_partial(" 12"), (Num(12.0), pointers(&[""])));
+    assert_eq!(parse_partial(" 12 "), (Num(12.0), vec![]));
+    assert_eq!(parse_partial("[1, 2."), (Arr(vec![Num(1.0), JsonData::None]), pointers(&["/1", ""])));
+    assert_eq!(parse_partial("[true, nu"), (Arr(vec![Bool(true), JsonData::None]), pointers(&["/1", ""])));
+    assert_eq!(parse_partial("[[],"), (Arr(vec![Arr(vec![])]), pointers(&[""])));
+    assert_eq!(
+        parse_partial(r#"{"a/b": {"c"#),
+        (obj([("a/b", obj([("c", JsonData::None)]))]), pointers(&["/a~1b/c", "/a~1b", ""]))
+    );
+    assert_eq!(
+        parse_partial(r#"{"a": 1, "b": [{}, "x"#),
+        (
+            obj([("a", Num(1.0)), ("b", Arr(vec![obj([]), text("x")]))]),
+            pointers(&["/b/1", "/b", ""])
+        )
+    );
+    let deep = ParseOptions {
+        max_depth: usize::MAX,
+        ..Default::default()
+    };
+    let (_, incomplete) = parse_partial_with(&format!("[1,{}\"x", "{\"~\":[".repeat(1000)), &deep);
+    assert_eq!(incomplete.len(), 2002);
+    assert_eq!(incomplete[0], "/1".to_string() + &"/~0/0".repeat(1000));
+    assert_eq!(incomplete[1], "/1".to_string() + &"/~0/0".repeat(999) + "/~0");
+    assert_eq!(incomplete[2000], "/1");
+    assert_eq!(parse_partial("[1] 2"), (Arr(vec![Num(1.0)]), vec![]));
+    assert_eq!(
+        parse_partial("[1 2"),
+        (
+            JsonData::Err("an error: expected ',' or ']' after array element, found '2' at 4:1".to_string()),
+            vec![]
+        )
+    );
+    let options = ParseOptions {
+        json5: true,
+        duplicate_keys: DuplicateKeys::Error,
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_partial_with("{a: 'x', /* b", &options),
+        (obj([("a", text("x"))]), pointers(&[""]))
+    );
+    assert_eq!(
+        parse_partial_with("{a: 1, a", &options),
+        (JsonData::Err("an error: duplicate key \"a\" at 8:1, first at 2:1".to_string()), vec![])
+    );
+}
+
+/// A xorshift generator making the property tests reproducible
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> usize {
+        (self.next() % n) as usize
+    }
+
+    fn number(&mut self) -> f64 {
+        match self.below(4) {
+            0 => self.below(1000) as f64 - 500.0,
+            1 => (self.next() as i64) as f64,
+            2 => self.below(1_000_000) as f64 / 1000.0,
+            _ => loop {
+                let num = f64::from_bits(self.next());
+                if num.is_finite() {
+                    break num;
+                }
+            },
+        }
+    }
+
+    fn text(&mut self) -> String {
+        const CHARS: [char; 16] = [
+            'a', 'Z', '0', ' ', '"', '\\', '/', '\n', '\t', '\0', '\u{1f}', '\u{7f}', 'é', '世',
+            '😀', '\u{2028}',
+        ];
+        (0..self.below(12)).map(|_| CHARS[self.below(CHARS.len() as u64)]).collect()
+    }
+
+    fn value(&mut self, depth: usize) -> JsonData {
+        match self.below(if depth < 4 { 7 } else { 4 }) {
+            0 => [Null, Bool(true), Bool(false)][self.below(3)].clone(),
+            1 => Num(self.number()),
+            2 | 3 => Text(self.text()),
+            4 | 5 => Arr((0..self.below(6)).map(|_| self.value(depth + 1)).collect()),
+            _ => Data(
+                (0..self.below(6))
+                    .map(|_| (self.text(), self.value(depth + 1)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut random = Random(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..2_000 {
+        let value = random.value(0);
+        let json = to_json(&value);
+        assert_eq!(parse(&json), value, "{json}");
+        assert_eq!(parse_fragment(&mut json.chars()).0, value, "{json}");
+        assert_eq!(parse_ref(&json).into_owned(), value, "{json}");
+        assert_eq!(JsonData::from(&JsonDoc::parse(&json).unwrap()), value, "{json}");
+        assert_eq!(to_json(&parse(&json)).len(), json.len(), "{json}")
+    }
+}
+
+#[test]
+fn round_trip_in_chunks() {
+    let mut random = Random(0x2545_F491_4F6C_DD1D);
+    for _ in 0..300 {
+        let values: Vec<JsonData> = (0..1 + random.below(4)).map(|_| random.value(0)).collect();
+        let json = values.iter().map(to_json).collect::<Vec<_>>().join(" ");
+        let mut parser = JsonParser::new();
+        let mut parsed = Vec::new();
+        let mut rest = json.as_str();
+        while !rest.is_empty() {
+            let mut split = random.below(rest.len() as u64 + 1);
+            while !rest.is_char_boundary(split) {
+                split += 1
+            }
+            if let Parsed::Values(chunk) = parser.feed(&rest[..split]) {
+                parsed.extend(chunk)
+            }
+            rest = &rest[split..]
+        }
+        parsed.extend(parser.finish());
+        assert_eq!(parsed, values, "{json}")
+    }
+}
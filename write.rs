@@ -0,0 +1,124 @@
+//! Serialization of `JsonData` to JSON text
+use std::fmt::Write;
+
+use crate::JsonData;
+
+/// How a number which isn't finite is written, standard JSON has no
+/// form for it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NonFinite {
+    /// `null`
+    #[default]
+    Null,
+    /// Bare `NaN`, `Infinity` and `-Infinity` as JSON5 has them, parsed back
+    /// with [crate::ParseOptions::non_finite]
+    Literal,
+    /// Strings `"NaN"`, `"Infinity"` and `"-Infinity"`
+    Text,
+}
+
+/// Options of serialization
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteOptions {
+    pub non_finite: NonFinite,
+}
+
+/// Serializes `JsonData` to compact JSON text
+///
+/// Parsing the text gives the same data back. Numbers are written in the
+/// shortest form keeping their value, a number which isn't finite,
+/// [JsonData::None] and [JsonData::Err] are written as `null`.
+/// ```
+/// let json = simjson::parse(r#"{"name":"Mal\"vika", "kids":["Ruth\n", 2.5e-7]}"#);
+/// assert_eq!(simjson::parse(&simjson::to_json(&json)), json);
+/// assert_eq!(simjson::to_json(&simjson::parse("[1.0, -0.5, 1e300]")), "[1,-0.5,1e300]");
+/// ```
+pub fn to_json(json: &JsonData) -> String {
+    to_json_with(json, &WriteOptions::default())
+}
+
+/// Serializes `JsonData` applying the options
+/// ```
+/// use simjson::{JsonData::{Arr, Num}, NonFinite, WriteOptions};
+/// let json = Arr(vec![Num(f64::NAN), Num(f64::NEG_INFINITY)]);
+/// let options = |non_finite| WriteOptions { non_finite };
+/// assert_eq!(simjson::to_json_with(&json, &options(NonFinite::Literal)), "[NaN,-Infinity]");
+/// assert_eq!(simjson::to_json_with(&json, &options(NonFinite::Text)), r#"["NaN","-Infinity"]"#);
+/// ```
+pub fn to_json_with(json: &JsonData, options: &WriteOptions) -> String {
+    let mut res = String::new();
+    write_value(&mut res, json, options);
+    res
+}
+
+fn write_value(res: &mut String, json: &JsonData, options: &WriteOptions) {
+    match json {
+        JsonData::Data(obj) => {
+            res.push('{');
+            for (inx, (key, value)) in obj.iter().enumerate() {
+                if inx > 0 {
+                    res.push(',')
+                }
+                write_str(res, key);
+                res.push(':');
+                write_value(res, value, options)
+            }
+            res.push('}')
+        }
+        JsonData::Arr(arr) => {
+            res.push('[');
+            for (inx, value) in arr.iter().enumerate() {
+                if inx > 0 {
+                    res.push(',')
+                }
+                write_value(res, value, options)
+            }
+            res.push(']')
+        }
+        JsonData::Text(text) => write_str(res, text),
+        JsonData::Num(num) => write_num(res, *num, options.non_finite),
+        JsonData::Bool(b) => res.push_str(if *b { "true" } else { "false" }),
+        JsonData::Null | JsonData::None | JsonData::Err(_) => res.push_str("null"),
+    }
+}
+
+/// Writes a number, integers without a fraction and others in
+/// the exponent form when it is shorter
+fn write_num(res: &mut String, num: f64, non_finite: NonFinite) {
+    if !num.is_finite() {
+        let name = if num.is_nan() {
+            "NaN"
+        } else if num > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        };
+        match non_finite {
+            NonFinite::Null => res.push_str("null"),
+            NonFinite::Literal => res.push_str(name),
+            NonFinite::Text => write_str(res, name),
+        }
+    } else if num.fract() == 0.0 && num.abs() < 1e16 {
+        write!(res, "{num}").unwrap()
+    } else {
+        write!(res, "{num:?}").unwrap()
+    }
+}
+
+fn write_str(res: &mut String, text: &str) {
+    res.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            '\u{8}' => res.push_str("\\b"),
+            '\u{c}' => res.push_str("\\f"),
+            '\0'..='\u{1f}' => write!(res, "\\u{:04x}", c as u32).unwrap(),
+            _ => res.push(c),
+        }
+    }
+    res.push('"')
+}